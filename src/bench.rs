@@ -0,0 +1,75 @@
+use crate::bvh::Bvh;
+use crate::object::{Object, ObjectBuilder};
+use nalgebra::{Point3, Vector3};
+use ncollide3d::query::{Ray, RayIntersection};
+use ncollide3d::shape;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+/// Compares tracing random rays through a scene of randomly placed spheres with and without
+/// the bounding volume hierarchy.
+pub fn run(spheres: usize, rays: usize) {
+    let mut rng = rand::thread_rng();
+
+    let objects = (0..spheres)
+        .map(|_| {
+            ObjectBuilder::new(shape::Ball::new(rng.gen_range(0.05, 0.2)))
+                .position(
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-20.0, 20.0),
+                    rng.gen_range(-60.0, -20.0),
+                )
+                .build()
+        })
+        .collect::<Vec<Object>>();
+
+    let rays = (0..rays)
+        .map(|_| {
+            let target = Vector3::new(
+                rng.gen_range(-20.0, 20.0),
+                rng.gen_range(-20.0, 20.0),
+                -40.0,
+            );
+            Ray::new(Point3::origin(), target.normalize())
+        })
+        .collect::<Vec<Ray<f64>>>();
+
+    let build_start = Instant::now();
+    let bvh = Bvh::new(&objects);
+    let build_time = build_start.elapsed();
+
+    let (linear_hits, linear_time) = measure(&rays, |ray| trace_linear(&objects, ray));
+    let (bvh_hits, bvh_time) = measure(&rays, |ray| bvh.trace(&objects, ray));
+
+    println!("{} spheres, {} rays", objects.len(), rays.len());
+    println!("bvh build: {:?}", build_time);
+    println!("linear:    {:?} ({} hits)", linear_time, linear_hits);
+    println!("bvh:       {:?} ({} hits)", bvh_time, bvh_hits);
+    println!(
+        "speedup:   {:.1}x",
+        linear_time.as_secs_f64() / bvh_time.as_secs_f64()
+    );
+}
+
+fn measure<'a>(
+    rays: &[Ray<f64>],
+    trace: impl Fn(&Ray<f64>) -> Option<(&'a Object, RayIntersection<f64>)>,
+) -> (usize, Duration) {
+    let start = Instant::now();
+    let hits = rays.iter().filter(|ray| trace(ray).is_some()).count();
+    (hits, start.elapsed())
+}
+
+fn trace_linear<'a>(
+    objects: &'a [Object],
+    ray: &Ray<f64>,
+) -> Option<(&'a Object, RayIntersection<f64>)> {
+    objects
+        .iter()
+        .filter_map(|object| {
+            object
+                .intersect(ray)
+                .map(|intersection| (object, intersection))
+        })
+        .min_by(|(_, a), (_, b)| a.toi.partial_cmp(&b.toi).unwrap())
+}
@@ -0,0 +1,323 @@
+use crate::color::Color;
+use crate::sampling::{self, SampleRng};
+use nalgebra::Vector3;
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Smallest GGX roughness, perfectly smooth surfaces would need a delta distribution
+const MIN_ALPHA: f64 = 1e-3;
+/// Reflectance at normal incidence of dielectrics in the glTF metallic/roughness model
+const DIELECTRIC_REFLECTANCE: f64 = 0.04;
+
+/// Scattering function of a non-specular surface. All directions point away from the surface
+/// and `normal` is on the side of `outgoing`.
+pub enum Bsdf {
+    Lambert { reflectance: Color },
+    Microfacet(Microfacet),
+    Dielectric(RoughDielectric),
+}
+
+impl Bsdf {
+    /// Whether light can pass through the surface to the side opposite of `normal`.
+    pub fn transmits(&self) -> bool {
+        matches!(self, Bsdf::Dielectric(_))
+    }
+
+    pub fn eval(
+        &self,
+        outgoing: &Vector3<f64>,
+        incoming: &Vector3<f64>,
+        normal: &Vector3<f64>,
+    ) -> Color {
+        match self {
+            Bsdf::Lambert { reflectance } => {
+                if normal.dot(incoming) > 0.0 {
+                    *reflectance / PI
+                } else {
+                    Color([0.0; 3])
+                }
+            }
+            Bsdf::Microfacet(microfacet) => microfacet.eval(outgoing, incoming, normal),
+            Bsdf::Dielectric(dielectric) => dielectric.eval(outgoing, incoming, normal),
+        }
+    }
+
+    /// Probability density with respect to solid angle of `sample` returning `incoming`.
+    pub fn pdf(
+        &self,
+        outgoing: &Vector3<f64>,
+        incoming: &Vector3<f64>,
+        normal: &Vector3<f64>,
+    ) -> f64 {
+        match self {
+            Bsdf::Lambert { .. } => normal.dot(incoming).max(0.0) / PI,
+            Bsdf::Microfacet(microfacet) => microfacet.pdf(outgoing, incoming, normal),
+            Bsdf::Dielectric(dielectric) => dielectric.pdf(outgoing, incoming, normal),
+        }
+    }
+
+    pub fn sample(
+        &self,
+        outgoing: &Vector3<f64>,
+        normal: &Vector3<f64>,
+        rng: &mut SampleRng,
+    ) -> Option<Vector3<f64>> {
+        match self {
+            Bsdf::Lambert { .. } => Some(sampling::cosine_hemisphere(normal, rng)),
+            Bsdf::Microfacet(microfacet) => microfacet.sample(outgoing, normal, rng),
+            Bsdf::Dielectric(dielectric) => dielectric.sample(outgoing, normal, rng),
+        }
+    }
+}
+
+/// glTF metallic/roughness material: a GGX specular lobe with Smith shadowing and Schlick
+/// Fresnel on top of a Lambertian base for the non-metallic part.
+pub struct Microfacet {
+    pub base_color: Color,
+    pub metallic: f64,
+    alpha: f64,
+}
+
+impl Microfacet {
+    pub fn new(base_color: Color, metallic: f64, roughness: f64) -> Microfacet {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Microfacet {
+            base_color,
+            metallic: metallic.clamp(0.0, 1.0),
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+        }
+    }
+
+    fn eval(
+        &self,
+        outgoing: &Vector3<f64>,
+        incoming: &Vector3<f64>,
+        normal: &Vector3<f64>,
+    ) -> Color {
+        let cos_out = normal.dot(outgoing);
+        let cos_in = normal.dot(incoming);
+        if cos_out <= 0.0 || cos_in <= 0.0 {
+            return Color([0.0; 3]);
+        }
+
+        let half = (outgoing + incoming).normalize();
+        let fresnel = self.fresnel(outgoing.dot(&half));
+        let specular = fresnel
+            * (self.distribution(normal.dot(&half)) * self.masking(cos_out) * self.masking(cos_in)
+                / (4.0 * cos_out * cos_in));
+        let diffuse = (Color([1.0; 3]) - fresnel) * self.base_color * ((1.0 - self.metallic) / PI);
+
+        specular + diffuse
+    }
+
+    fn pdf(&self, outgoing: &Vector3<f64>, incoming: &Vector3<f64>, normal: &Vector3<f64>) -> f64 {
+        let cos_in = normal.dot(incoming);
+        if normal.dot(outgoing) <= 0.0 || cos_in <= 0.0 {
+            return 0.0;
+        }
+
+        let half = (outgoing + incoming).normalize();
+        let cos_half = normal.dot(&half);
+        let specular_pdf =
+            self.distribution(cos_half) * cos_half / (4.0 * outgoing.dot(&half).abs());
+        let specular_probability = self.specular_probability();
+
+        specular_probability * specular_pdf + (1.0 - specular_probability) * cos_in / PI
+    }
+
+    fn sample(
+        &self,
+        outgoing: &Vector3<f64>,
+        normal: &Vector3<f64>,
+        rng: &mut SampleRng,
+    ) -> Option<Vector3<f64>> {
+        let incoming = if rng.gen::<f64>() < self.specular_probability() {
+            let half = sample_half_vector(self.alpha, normal, rng);
+            2.0 * outgoing.dot(&half) * half - outgoing
+        } else {
+            sampling::cosine_hemisphere(normal, rng)
+        };
+
+        (normal.dot(&incoming) > 0.0).then_some(incoming)
+    }
+
+    /// Metals have no diffuse part, so fully metallic surfaces only sample their specular lobe.
+    fn specular_probability(&self) -> f64 {
+        0.5 + 0.5 * self.metallic
+    }
+
+    fn distribution(&self, cos_half: f64) -> f64 {
+        distribution(self.alpha, cos_half)
+    }
+
+    fn masking(&self, cos: f64) -> f64 {
+        masking(self.alpha, cos)
+    }
+
+    fn fresnel(&self, cos: f64) -> Color {
+        let reflectance = Color([DIELECTRIC_REFLECTANCE; 3]) * (1.0 - self.metallic)
+            + self.base_color * self.metallic;
+        let weight = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+        reflectance * (1.0 - weight) + Color([weight; 3])
+    }
+}
+
+/// Frosted glass reflecting and transmitting through a GGX microfacet surface following
+/// Walter et al., "Microfacet Models for Refraction through Rough Surfaces".
+pub struct RoughDielectric {
+    pub tint: Color,
+    /// Ratio of the refraction index behind the surface to the one on the side of `outgoing`
+    eta: f64,
+    alpha: f64,
+}
+
+impl RoughDielectric {
+    pub fn new(tint: Color, index: f64, roughness: f64, entering: bool) -> RoughDielectric {
+        let roughness = roughness.clamp(0.0, 1.0);
+        RoughDielectric {
+            tint,
+            eta: if entering { index } else { 1.0 / index },
+            alpha: (roughness * roughness).max(MIN_ALPHA),
+        }
+    }
+
+    fn eval(
+        &self,
+        outgoing: &Vector3<f64>,
+        incoming: &Vector3<f64>,
+        normal: &Vector3<f64>,
+    ) -> Color {
+        let cos_out = normal.dot(outgoing);
+        let cos_in = normal.dot(incoming);
+        let half = match self.half_vector(outgoing, incoming, normal) {
+            Some(half) => half,
+            None => return Color([0.0; 3]),
+        };
+
+        let out_dot_half = outgoing.dot(&half);
+        let in_dot_half = incoming.dot(&half);
+        let fresnel = fresnel_dielectric(out_dot_half, self.eta);
+        let geometry = distribution(self.alpha, normal.dot(&half))
+            * masking(self.alpha, cos_out.abs())
+            * masking(self.alpha, cos_in.abs());
+
+        let value = if cos_in > 0.0 {
+            fresnel * geometry / (4.0 * cos_out * cos_in)
+        } else {
+            // Radiance is compressed into the denser medium, which cancels the eta² of the
+            // Jacobian of the refracted direction
+            let denominator = out_dot_half + self.eta * in_dot_half;
+            (1.0 - fresnel) * geometry * (out_dot_half * in_dot_half).abs()
+                / ((cos_out * cos_in).abs() * denominator * denominator)
+        };
+
+        self.tint * value
+    }
+
+    fn pdf(&self, outgoing: &Vector3<f64>, incoming: &Vector3<f64>, normal: &Vector3<f64>) -> f64 {
+        let half = match self.half_vector(outgoing, incoming, normal) {
+            Some(half) => half,
+            None => return 0.0,
+        };
+
+        let out_dot_half = outgoing.dot(&half);
+        let in_dot_half = incoming.dot(&half);
+        let fresnel = fresnel_dielectric(out_dot_half, self.eta);
+        let half_pdf = distribution(self.alpha, normal.dot(&half)) * normal.dot(&half);
+
+        if normal.dot(incoming) > 0.0 {
+            fresnel * half_pdf / (4.0 * out_dot_half.abs())
+        } else {
+            let denominator = out_dot_half + self.eta * in_dot_half;
+            (1.0 - fresnel) * half_pdf * self.eta * self.eta * in_dot_half.abs()
+                / (denominator * denominator)
+        }
+    }
+
+    fn sample(
+        &self,
+        outgoing: &Vector3<f64>,
+        normal: &Vector3<f64>,
+        rng: &mut SampleRng,
+    ) -> Option<Vector3<f64>> {
+        let half = sample_half_vector(self.alpha, normal, rng);
+        let cos_out = outgoing.dot(&half);
+        if cos_out <= 0.0 {
+            return None;
+        }
+
+        let fresnel = fresnel_dielectric(cos_out, self.eta);
+        if rng.gen::<f64>() < fresnel {
+            let incoming = 2.0 * cos_out * half - outgoing;
+            (normal.dot(&incoming) > 0.0).then_some(incoming)
+        } else {
+            let sin_squared = (1.0 - cos_out * cos_out) / (self.eta * self.eta);
+            let cos_transmitted = (1.0 - sin_squared).max(0.0).sqrt();
+            let incoming = -outgoing / self.eta + (cos_out / self.eta - cos_transmitted) * half;
+            (normal.dot(&incoming) < 0.0).then(|| incoming.normalize())
+        }
+    }
+
+    /// Microfacet normal on the side of `normal` which scatters `outgoing` into `incoming`.
+    fn half_vector(
+        &self,
+        outgoing: &Vector3<f64>,
+        incoming: &Vector3<f64>,
+        normal: &Vector3<f64>,
+    ) -> Option<Vector3<f64>> {
+        let cos_out = normal.dot(outgoing);
+        let cos_in = normal.dot(incoming);
+        if cos_out <= 0.0 || cos_in == 0.0 {
+            return None;
+        }
+
+        let half = if cos_in > 0.0 {
+            outgoing + incoming
+        } else {
+            -(outgoing + incoming * self.eta)
+        };
+        let half = half.try_normalize(f64::EPSILON)?;
+        let half = if normal.dot(&half) < 0.0 { -half } else { half };
+
+        // Each direction has to lie on the side of the microfacet matching its side of the surface
+        (outgoing.dot(&half) > 0.0 && incoming.dot(&half) * cos_in > 0.0).then_some(half)
+    }
+}
+
+/// Microfacet normal distributed proportional to D(h) * cos(h).
+fn sample_half_vector(alpha: f64, normal: &Vector3<f64>, rng: &mut SampleRng) -> Vector3<f64> {
+    let u = rng.gen::<f64>();
+    let cos_theta = ((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    let (tangent, bitangent) = sampling::orthonormal_basis(normal);
+    tangent * (sin_theta * angle.cos()) + bitangent * (sin_theta * angle.sin()) + normal * cos_theta
+}
+
+/// GGX normal distribution.
+fn distribution(alpha: f64, cos_half: f64) -> f64 {
+    let alpha_squared = alpha * alpha;
+    let denominator = cos_half * cos_half * (alpha_squared - 1.0) + 1.0;
+    alpha_squared / (PI * denominator * denominator)
+}
+
+/// Smith masking function for GGX.
+fn masking(alpha: f64, cos: f64) -> f64 {
+    let alpha_squared = alpha * alpha;
+    2.0 * cos / (cos + (alpha_squared + (1.0 - alpha_squared) * cos * cos).sqrt())
+}
+
+/// Unpolarized Fresnel reflectance at a boundary hit at the cosine `cos`, `eta` being the ratio
+/// of the refraction index behind the boundary to the one in front of it.
+fn fresnel_dielectric(cos: f64, eta: f64) -> f64 {
+    let sin_squared = (1.0 - cos * cos) / (eta * eta);
+    if sin_squared >= 1.0 {
+        //Total internal reflection
+        return 1.0;
+    }
+
+    let cos_transmitted = (1.0 - sin_squared).sqrt();
+    let r_s = (cos - eta * cos_transmitted) / (cos + eta * cos_transmitted);
+    let r_p = (eta * cos - cos_transmitted) / (eta * cos + cos_transmitted);
+    (r_s * r_s + r_p * r_p) / 2.0
+}
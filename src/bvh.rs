@@ -0,0 +1,374 @@
+use crate::object::Object;
+use nalgebra::Vector3;
+use ncollide3d::bounding_volume::{BoundingVolume, AABB};
+use ncollide3d::query::{Ray, RayIntersection};
+
+const BUCKETS: usize = 16;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f64 = 1.0;
+const INTERSECTION_COST: f64 = 1.0;
+
+/// Bounding volume hierarchy over the bounded objects of a scene, built with the surface area
+/// heuristic. Unbounded objects like planes are kept in a separate list and tested for every ray.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+    unbounded: Vec<usize>,
+}
+
+struct Node {
+    aabb: AABB<f64>,
+    kind: NodeKind,
+}
+
+enum NodeKind {
+    Leaf { first: usize, count: usize },
+    // The left child always directly follows its parent
+    Interior { right: usize, axis: usize },
+}
+
+struct Primitive {
+    index: usize,
+    aabb: AABB<f64>,
+    centroid: Vector3<f64>,
+}
+
+impl Bvh {
+    pub fn new(objects: &[Object]) -> Bvh {
+        let mut unbounded = Vec::new();
+        let mut primitives = Vec::new();
+
+        for (index, object) in objects.iter().enumerate() {
+            match object.aabb() {
+                Some(aabb) => primitives.push(Primitive {
+                    index,
+                    centroid: aabb.center().coords,
+                    aabb,
+                }),
+                None => unbounded.push(index),
+            }
+        }
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(primitives.len() * 2),
+            indices: Vec::with_capacity(primitives.len()),
+            unbounded,
+        };
+
+        if !primitives.is_empty() {
+            bvh.build(&mut primitives);
+        }
+
+        bvh
+    }
+
+    fn build(&mut self, primitives: &mut [Primitive]) -> usize {
+        let aabb = primitives
+            .iter()
+            .skip(1)
+            .fold(primitives[0].aabb.clone(), |aabb, primitive| {
+                aabb.merged(&primitive.aabb)
+            });
+
+        let node_index = self.nodes.len();
+        self.nodes.push(Node {
+            aabb,
+            kind: NodeKind::Leaf { first: 0, count: 0 },
+        });
+
+        let split = if primitives.len() > 1 {
+            Self::find_split(primitives, &self.nodes[node_index].aabb)
+        } else {
+            None
+        };
+
+        self.nodes[node_index].kind = match split {
+            Some((mid, axis)) => {
+                let (left, right) = primitives.split_at_mut(mid);
+                self.build(left);
+                NodeKind::Interior {
+                    right: self.build(right),
+                    axis,
+                }
+            }
+            None => {
+                let first = self.indices.len();
+                self.indices
+                    .extend(primitives.iter().map(|primitive| primitive.index));
+                NodeKind::Leaf {
+                    first,
+                    count: primitives.len(),
+                }
+            }
+        };
+
+        node_index
+    }
+
+    /// Partitions the primitives along the axis with the largest centroid extent at the bucket
+    /// boundary with the lowest surface area heuristic cost. Returns the split index and axis or
+    /// `None` if a leaf is cheaper.
+    fn find_split(primitives: &mut [Primitive], aabb: &AABB<f64>) -> Option<(usize, usize)> {
+        let (min, max) = primitives.iter().fold(
+            (
+                Vector3::repeat(f64::INFINITY),
+                Vector3::repeat(f64::NEG_INFINITY),
+            ),
+            |(min, max), primitive| (min.inf(&primitive.centroid), max.sup(&primitive.centroid)),
+        );
+        let axis = (max - min).imax();
+        let extent = max[axis] - min[axis];
+
+        if extent <= 0.0 {
+            return (primitives.len() > MAX_LEAF_SIZE).then_some((primitives.len() / 2, axis));
+        }
+
+        let bucket_of = |primitive: &Primitive| {
+            let offset = (primitive.centroid[axis] - min[axis]) / extent;
+            ((offset * BUCKETS as f64) as usize).min(BUCKETS - 1)
+        };
+
+        let mut buckets: Vec<(usize, Option<AABB<f64>>)> = vec![(0, None); BUCKETS];
+        for primitive in primitives.iter() {
+            let bucket = &mut buckets[bucket_of(primitive)];
+            bucket.0 += 1;
+            bucket.1 = Some(merge(bucket.1.take(), &primitive.aabb));
+        }
+
+        let (best_bucket, best_cost) = (0..BUCKETS - 1)
+            .map(|split| {
+                let side_cost = |buckets: &[(usize, Option<AABB<f64>>)]| {
+                    let (count, aabb) = buckets.iter().fold(
+                        (0, None),
+                        |(count, aabb), (bucket_count, bucket_aabb)| match bucket_aabb {
+                            Some(bucket_aabb) => {
+                                (count + bucket_count, Some(merge(aabb, bucket_aabb)))
+                            }
+                            None => (count, aabb),
+                        },
+                    );
+                    aabb.map_or(0.0, |aabb| count as f64 * surface_area(&aabb))
+                };
+
+                let cost = TRAVERSAL_COST
+                    + INTERSECTION_COST
+                        * (side_cost(&buckets[..=split]) + side_cost(&buckets[split + 1..]))
+                        / surface_area(aabb);
+                (split, cost)
+            })
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            .unwrap();
+
+        let leaf_cost = INTERSECTION_COST * primitives.len() as f64;
+        if primitives.len() <= MAX_LEAF_SIZE && leaf_cost <= best_cost {
+            return None;
+        }
+
+        let mut mid = 0;
+        for i in 0..primitives.len() {
+            if bucket_of(&primitives[i]) <= best_bucket {
+                primitives.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        if mid == 0 || mid == primitives.len() {
+            Some((primitives.len() / 2, axis))
+        } else {
+            Some((mid, axis))
+        }
+    }
+
+    pub fn trace<'a>(
+        &self,
+        objects: &'a [Object],
+        ray: &Ray<f64>,
+    ) -> Option<(&'a Object, RayIntersection<f64>)> {
+        let mut closest: Option<(&'a Object, RayIntersection<f64>)> = None;
+        let test = |index: usize, closest: &mut Option<(&'a Object, RayIntersection<f64>)>| {
+            let object = &objects[index];
+            if let Some(intersection) = object.intersect(ray) {
+                if closest
+                    .as_ref()
+                    .map_or(true, |(_, best)| intersection.toi < best.toi)
+                {
+                    *closest = Some((object, intersection));
+                }
+            }
+        };
+
+        for &index in &self.unbounded {
+            test(index, &mut closest);
+        }
+
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let inverse_dir = ray.dir.map(|value| 1.0 / value);
+        let mut stack = vec![0];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let max_toi = closest.as_ref().map_or(f64::INFINITY, |(_, best)| best.toi);
+
+            if !hits_aabb(&node.aabb, ray, &inverse_dir, max_toi) {
+                continue;
+            }
+
+            match node.kind {
+                NodeKind::Leaf { first, count } => {
+                    for &index in &self.indices[first..first + count] {
+                        test(index, &mut closest);
+                    }
+                }
+                NodeKind::Interior { right, axis } => {
+                    let left = node_index + 1;
+                    // Visit the child closer to the ray origin first
+                    if ray.dir[axis] < 0.0 {
+                        stack.push(left);
+                        stack.push(right);
+                    } else {
+                        stack.push(right);
+                        stack.push(left);
+                    }
+                }
+            }
+        }
+
+        closest
+    }
+}
+
+fn merge(aabb: Option<AABB<f64>>, other: &AABB<f64>) -> AABB<f64> {
+    match aabb {
+        Some(aabb) => aabb.merged(other),
+        None => other.clone(),
+    }
+}
+
+fn surface_area(aabb: &AABB<f64>) -> f64 {
+    let extents = aabb.extents();
+    2.0 * (extents.x * extents.y + extents.y * extents.z + extents.z * extents.x)
+}
+
+fn hits_aabb(aabb: &AABB<f64>, ray: &Ray<f64>, inverse_dir: &Vector3<f64>, max_toi: f64) -> bool {
+    let mut t_min = 0.0;
+    let mut t_max = max_toi;
+
+    for axis in 0..3 {
+        let origin = ray.origin[axis];
+        let (min, max) = (aabb.mins()[axis], aabb.maxs()[axis]);
+        // Parallel to the slab, zero times infinity would be NaN for origins on its faces
+        if ray.dir[axis] == 0.0 {
+            if origin < min || origin > max {
+                return false;
+            }
+            continue;
+        }
+        let t1 = (min - origin) * inverse_dir[axis];
+        let t2 = (max - origin) * inverse_dir[axis];
+        t_min = t1.min(t2).max(t_min);
+        t_max = t1.max(t2).min(t_max);
+    }
+
+    t_max >= t_min
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::ObjectBuilder;
+    use crate::sampling::{self, SampleRng};
+    use nalgebra::Point3;
+    use ncollide3d::shape;
+    use rand::{Rng, SeedableRng};
+
+    fn linear_scan<'a>(
+        objects: &'a [Object],
+        ray: &Ray<f64>,
+    ) -> Option<(&'a Object, RayIntersection<f64>)> {
+        objects
+            .iter()
+            .filter_map(|object| object.intersect(ray).map(|hit| (object, hit)))
+            .min_by(|(_, a), (_, b)| a.toi.partial_cmp(&b.toi).unwrap())
+    }
+
+    #[test]
+    fn trace_matches_linear_scan() {
+        let mut rng = SampleRng::seed_from_u64(4);
+        // Integer positions and radii put bounding box faces on the grid of the ray origins.
+        // Spheres don't overlap so no two objects are hit at the same distance.
+        let mut spheres: Vec<(Point3<f64>, f64)> = Vec::new();
+        while spheres.len() < 60 {
+            let center = Point3::new(
+                rng.gen_range(-10, 11) as f64,
+                rng.gen_range(-10, 11) as f64,
+                rng.gen_range(-10, 11) as f64,
+            );
+            let radius = rng.gen_range(1, 3) as f64;
+            if spheres
+                .iter()
+                .all(|(other, other_radius)| (center - other).norm() > radius + other_radius)
+            {
+                spheres.push((center, radius));
+            }
+        }
+        let mut objects = spheres
+            .iter()
+            .map(|(center, radius)| {
+                ObjectBuilder::new(shape::Ball::new(*radius))
+                    .position(center.x, center.y, center.z)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        objects.push(
+            ObjectBuilder::new(shape::Plane::new(Vector3::y_axis()))
+                .position(0.0, -13.0, 0.0)
+                .build(),
+        );
+        let bvh = Bvh::new(&objects);
+
+        let axes = [
+            Vector3::x(),
+            -Vector3::x(),
+            Vector3::y(),
+            -Vector3::y(),
+            Vector3::z(),
+            -Vector3::z(),
+        ];
+        let mut hits = 0;
+        for i in 0..2000 {
+            let origin = Point3::new(
+                rng.gen_range(-15, 16) as f64,
+                rng.gen_range(-15, 16) as f64,
+                rng.gen_range(-15, 16) as f64,
+            );
+            // Axis parallel directions have infinite components in their inverse
+            let dir = if i % 2 == 0 {
+                axes[rng.gen_range(0, axes.len())]
+            } else {
+                sampling::uniform_sphere(&mut rng)
+            };
+            let ray = Ray::new(origin, dir);
+
+            let expected = linear_scan(&objects, &ray);
+            let actual = bvh.trace(&objects, &ray);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some((expected, expected_hit)), Some((actual, actual_hit))) => {
+                    assert!(std::ptr::eq(expected, actual), "{:?}", ray);
+                    assert_eq!(expected_hit.toi, actual_hit.toi, "{:?}", ray);
+                    hits += 1;
+                }
+                (expected, actual) => panic!(
+                    "{:?}: expected hit {}, got hit {}",
+                    ray,
+                    expected.is_some(),
+                    actual.is_some()
+                ),
+            }
+        }
+        assert!(hits > 500, "only {} rays hit anything", hits);
+    }
+}
@@ -0,0 +1,36 @@
+use nalgebra::{Point3, UnitQuaternion, Vector3};
+
+pub struct Camera {
+    pub position: Point3<f64>,
+    pub rotation: UnitQuaternion<f64>,
+    /// Vertical field of view in degree
+    pub fov: f64,
+    /// Radius of the thin lens, 0 for a pinhole camera
+    pub aperture: f64,
+    /// Distance to the plane in perfect focus
+    pub focus_distance: f64,
+}
+
+impl Camera {
+    pub fn look_at(
+        position: Point3<f64>,
+        target: Point3<f64>,
+        up: Vector3<f64>,
+        fov: f64,
+    ) -> Camera {
+        Camera {
+            position,
+            // The camera looks down its local -Z axis
+            rotation: UnitQuaternion::face_towards(&(position - target), &up),
+            fov,
+            aperture: 0.0,
+            focus_distance: (target - position).norm(),
+        }
+    }
+
+    pub fn lens(mut self, aperture: f64, focus_distance: f64) -> Self {
+        self.aperture = aperture;
+        self.focus_distance = focus_distance;
+        self
+    }
+}
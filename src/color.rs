@@ -1,26 +1,26 @@
 use std::iter::Sum;
-use std::ops::{Add, Div, Mul};
+use std::ops::{Add, Div, Mul, Sub};
 
 #[derive(Clone, Copy)]
 pub struct Color(pub [f64; 3]);
 
-const GAMMA: f64 = 2.2;
-
 impl Color {
+    /// Encodes the color for 8 bit images with the sRGB transfer function.
     pub fn to_u8(&self) -> [u8; 4] {
         let [r, g, b] = self.clamp().0;
-        [
-            (r.powf(1.0 / GAMMA) * 255.0) as u8,
-            (g.powf(1.0 / GAMMA) * 255.0) as u8,
-            (b.powf(1.0 / GAMMA) * 255.0) as u8,
-            255,
-        ]
+        let encode = |value: f64| (linear_to_srgb(value) * 255.0).round() as u8;
+        [encode(r), encode(g), encode(b), 255]
     }
 
     pub fn clamp(&self) -> Color {
         let [r, g, b] = self.0;
         Color([r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0)])
     }
+
+    pub fn max_component(&self) -> f64 {
+        let [r, g, b] = self.0;
+        r.max(g).max(b)
+    }
 }
 
 impl From<[f64; 3]> for Color {
@@ -29,10 +29,12 @@ impl From<[f64; 3]> for Color {
     }
 }
 
+/// Decodes an 8 bit sRGB color to linear values.
 impl From<[u8; 3]> for Color {
     fn from(color: [u8; 3]) -> Self {
         let [r, g, b] = color;
-        Color::from([r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0])
+        let decode = |value: u8| srgb_to_linear(value as f64 / 255.0);
+        Color([decode(r), decode(g), decode(b)])
     }
 }
 
@@ -46,6 +48,16 @@ impl Add for Color {
     }
 }
 
+impl Sub for Color {
+    type Output = Color;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        let [r, g, b] = self.0;
+        let [rr, gg, bb] = rhs.0;
+        Color([r - rr, g - gg, b - bb])
+    }
+}
+
 impl Mul for Color {
     type Output = Color;
 
@@ -79,3 +91,68 @@ impl Sum for Color {
         iter.fold(Color([0.0; 3]), |acc, color| acc + color)
     }
 }
+
+/// The sRGB electro-optical transfer function, converts an encoded value between zero and one
+/// to linear light.
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// The inverse of `srgb_to_linear`, encodes linear light between zero and one.
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn transfer_function_end_points() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert_close(srgb_to_linear(1.0), 1.0, 1e-12);
+        assert_close(linear_to_srgb(1.0), 1.0, 1e-12);
+    }
+
+    #[test]
+    fn transfer_function_breakpoints() {
+        assert_close(srgb_to_linear(0.04045), 0.04045 / 12.92, 1e-12);
+        assert_close(linear_to_srgb(0.0031308), 0.0031308 * 12.92, 1e-12);
+        // Both segments meet at the breakpoints
+        assert_close(srgb_to_linear(0.04045 + 1e-9), 0.04045 / 12.92, 1e-7);
+        assert_close(linear_to_srgb(0.0031308 + 1e-9), 0.0031308 * 12.92, 1e-7);
+    }
+
+    #[test]
+    fn transfer_function_reference_values() {
+        assert_close(srgb_to_linear(0.5), 0.21404, 1e-5);
+        assert_close(linear_to_srgb(0.21404), 0.5, 1e-5);
+        assert_close(Color::from([128, 128, 128]).0[0], 0.2158605, 1e-7);
+    }
+
+    #[test]
+    fn every_8_bit_value_survives_a_round_trip() {
+        for value in 0..=255 {
+            let color = Color::from([value, value, value]);
+            assert_eq!(color.to_u8(), [value, value, value, 255]);
+        }
+    }
+}
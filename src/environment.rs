@@ -0,0 +1,181 @@
+use crate::color::Color;
+use crate::sampling::{self, SampleRng};
+use crate::sky::Sky;
+use image::codecs::hdr::HdrDecoder;
+use image::ImageResult;
+use nalgebra::Vector3;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Light arriving from all directions that miss the objects of the scene.
+#[derive(Clone)]
+pub enum Environment {
+    Map(EnvironmentMap),
+    Sky(Sky),
+}
+
+impl Environment {
+    /// Light arriving from `direction`.
+    pub fn radiance(&self, direction: &Vector3<f64>) -> Color {
+        match self {
+            Environment::Map(map) => map.radiance(direction),
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// Samples a direction and returns it with its density with respect to solid angle.
+    pub fn sample(&self, rng: &mut SampleRng) -> Option<(Vector3<f64>, f64)> {
+        match self {
+            Environment::Map(map) => map.sample(rng),
+            // The sky varies slowly, its sun is sampled as a directional light
+            Environment::Sky(_) => Some((sampling::uniform_sphere(rng), 1.0 / (4.0 * PI))),
+        }
+    }
+
+    /// Density of `sample` returning `direction` with respect to solid angle.
+    pub fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            Environment::Sky(_) => 1.0 / (4.0 * PI),
+        }
+    }
+}
+
+/// Light arriving from all directions given by an equirectangular image. The center of the
+/// image lies in the direction of the negative z axis, the top row straight up.
+#[derive(Clone)]
+pub struct EnvironmentMap {
+    pixels: Arc<Vec<Color>>,
+    width: usize,
+    height: usize,
+    /// Cumulative distribution of the rows followed by one of the pixels within every row, the
+    /// pixels are weighted by their luminance and the solid angle they cover
+    row_cdf: Arc<Vec<f64>>,
+    pixel_cdfs: Arc<Vec<f64>>,
+}
+
+impl EnvironmentMap {
+    /// Loads a Radiance `.hdr` image scaled by `intensity`.
+    pub fn load(path: impl AsRef<Path>, intensity: f64) -> ImageResult<EnvironmentMap> {
+        let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|pixel| {
+                let [r, g, b] = pixel.0;
+                Color([r as f64, g as f64, b as f64]) * intensity
+            })
+            .collect();
+
+        Ok(Self::new(
+            pixels,
+            metadata.width as usize,
+            metadata.height as usize,
+        ))
+    }
+
+    fn new(pixels: Vec<Color>, width: usize, height: usize) -> EnvironmentMap {
+        let mut row_cdf = vec![0.0; height + 1];
+        let mut pixel_cdfs = vec![0.0; height * (width + 1)];
+
+        for y in 0..height {
+            let sin_theta = ((y as f64 + 0.5) / height as f64 * PI).sin();
+            let cdf = &mut pixel_cdfs[y * (width + 1)..(y + 1) * (width + 1)];
+            for x in 0..width {
+                cdf[x + 1] = cdf[x] + luminance(pixels[y * width + x]) * sin_theta;
+            }
+            row_cdf[y + 1] = row_cdf[y] + cdf[width];
+        }
+
+        EnvironmentMap {
+            pixels: Arc::new(pixels),
+            width,
+            height,
+            row_cdf: Arc::new(row_cdf),
+            pixel_cdfs: Arc::new(pixel_cdfs),
+        }
+    }
+
+    /// Light arriving from `direction`.
+    pub fn radiance(&self, direction: &Vector3<f64>) -> Color {
+        let (x, y) = self.pixel(direction);
+        self.pixels[y * self.width + x]
+    }
+
+    /// Samples a direction proportional to the brightness of the image and returns it with its
+    /// density with respect to solid angle, `None` for a black image.
+    pub fn sample(&self, rng: &mut SampleRng) -> Option<(Vector3<f64>, f64)> {
+        let total = self.row_cdf[self.height];
+        if total <= 0.0 {
+            return None;
+        }
+
+        let (y, v) = sample_cdf(&self.row_cdf, rng.gen::<f64>() * total);
+        let cdf = &self.pixel_cdfs[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let (_, u) = sample_cdf(cdf, rng.gen::<f64>() * cdf[self.width]);
+
+        let u = u / self.width as f64;
+        let v = v / self.height as f64;
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = v * PI;
+        let direction = Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+
+        let pdf = self.pdf(&direction);
+        (pdf > 0.0).then(|| (direction, pdf))
+    }
+
+    /// Density of `sample` returning `direction` with respect to solid angle.
+    pub fn pdf(&self, direction: &Vector3<f64>) -> f64 {
+        let total = self.row_cdf[self.height];
+        let (x, y) = self.pixel(direction);
+        let sin_theta = (1.0 - direction.y * direction.y).max(0.0).sqrt();
+        if total <= 0.0 || sin_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let row = y * (self.width + 1);
+        let weight = self.pixel_cdfs[row + x + 1] - self.pixel_cdfs[row + x];
+        // The image covers 2 pi by pi radians, a pixel row shrinks with the sine of its angle
+        weight / total * (self.width * self.height) as f64 / (2.0 * PI * PI * sin_theta)
+    }
+
+    fn pixel(&self, direction: &Vector3<f64>) -> (usize, usize) {
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2.0 * PI);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        (x, y)
+    }
+}
+
+fn luminance(color: Color) -> f64 {
+    let [r, g, b] = color.0;
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+/// Finds the entry of a piecewise constant distribution containing `value` and returns its
+/// index together with the continuous position within the distribution.
+fn sample_cdf(cdf: &[f64], value: f64) -> (usize, f64) {
+    // The first entry whose upper bound exceeds the value, which skips entries of zero weight
+    let index = match cdf[1..].binary_search_by(|bound| bound.partial_cmp(&value).unwrap()) {
+        Ok(index) => index + 1,
+        Err(index) => index,
+    }
+    .min(cdf.len() - 2);
+    let width = cdf[index + 1] - cdf[index];
+    let offset = if width > 0.0 {
+        (value - cdf[index]) / width
+    } else {
+        0.5
+    };
+    (index, index as f64 + offset)
+}
@@ -0,0 +1,43 @@
+use crate::color::Color;
+use crate::integrator::{Integrator, SHADOW_BIAS};
+use crate::sampling::{self, SampleRng};
+use crate::scene::Scene;
+use ncollide3d::query::Ray;
+
+/// Shades the first hit by how much of its hemisphere is unoccluded within `distance`.
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        AmbientOcclusion { distance: 1.0 }
+    }
+}
+
+impl Integrator for AmbientOcclusion {
+    fn radiance(&self, scene: &Scene, ray: Ray<f64>, rng: &mut SampleRng) -> Color {
+        let (_, intersection) = match scene.trace(&ray) {
+            Some(hit) => hit,
+            None => return Color([0.0; 3]),
+        };
+
+        let normal = if intersection.normal.dot(&ray.dir) > 0.0 {
+            -intersection.normal
+        } else {
+            intersection.normal
+        };
+        let origin = ray.point_at(intersection.toi) + normal * SHADOW_BIAS;
+        let occlusion_ray = Ray::new(origin, sampling::cosine_hemisphere(&normal, rng));
+
+        let occluded = scene
+            .trace(&occlusion_ray)
+            .map_or(false, |(_, intersection)| intersection.toi < self.distance);
+
+        if occluded {
+            Color([0.0; 3])
+        } else {
+            Color([1.0; 3])
+        }
+    }
+}
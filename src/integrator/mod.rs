@@ -0,0 +1,58 @@
+use crate::color::Color;
+use crate::sampling::SampleRng;
+use crate::scene::Scene;
+use nalgebra::Vector3;
+use ncollide3d::query::Ray;
+
+mod ambient_occlusion;
+mod normals;
+mod path;
+mod whitted;
+
+pub use ambient_occlusion::AmbientOcclusion;
+pub use normals::Normals;
+pub use path::PathTracer;
+pub use whitted::Whitted;
+
+pub const SHADOW_BIAS: f64 = 1e-13;
+
+/// Names accepted by `by_name`.
+pub const NAMES: [&str; 4] = ["whitted", "path", "ambient_occlusion", "normals"];
+
+/// Rendering algorithm computing the color seen along a primary ray.
+pub trait Integrator: Sync {
+    fn radiance(&self, scene: &Scene, ray: Ray<f64>, rng: &mut SampleRng) -> Color;
+}
+
+pub fn by_name(name: &str) -> Option<Box<dyn Integrator>> {
+    match name {
+        "whitted" => Some(Box::new(Whitted)),
+        "path" => Some(Box::new(PathTracer)),
+        "ambient_occlusion" => Some(Box::new(AmbientOcclusion::default())),
+        "normals" => Some(Box::new(Normals)),
+        _ => None,
+    }
+}
+
+/// Fraction of light reflected at the boundary of a dielectric with the given refraction index.
+pub fn fresnel(incident: Vector3<f64>, normal: Vector3<f64>, index: f64) -> f64 {
+    let i_dot_n = incident.dot(&normal).clamp(-1.0, 1.0);
+    let mut eta_i = 1.0;
+    let mut eta_t = index;
+    if i_dot_n > 0.0 {
+        eta_i = eta_t;
+        eta_t = 1.0;
+    }
+
+    let sin_t = eta_i / eta_t * (1.0 - i_dot_n * i_dot_n).max(0.0).sqrt();
+    if sin_t >= 1.0 {
+        //Total internal reflection
+        1.0
+    } else {
+        let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
+        let cos_i = i_dot_n.abs();
+        let r_s = ((eta_t * cos_i) - (eta_i * cos_t)) / ((eta_t * cos_i) + (eta_i * cos_t));
+        let r_p = ((eta_i * cos_i) - (eta_t * cos_t)) / ((eta_i * cos_i) + (eta_t * cos_t));
+        (r_s * r_s + r_p * r_p) / 2.0
+    }
+}
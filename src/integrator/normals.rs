@@ -0,0 +1,20 @@
+use crate::color::Color;
+use crate::integrator::Integrator;
+use crate::sampling::SampleRng;
+use crate::scene::Scene;
+use ncollide3d::query::Ray;
+
+/// Debug view mapping the world space surface normal at the first hit to a color.
+pub struct Normals;
+
+impl Integrator for Normals {
+    fn radiance(&self, scene: &Scene, ray: Ray<f64>, _rng: &mut SampleRng) -> Color {
+        scene
+            .trace(&ray)
+            .map(|(_, intersection)| {
+                let normal = intersection.normal.map(|value| value * 0.5 + 0.5);
+                Color([normal.x, normal.y, normal.z])
+            })
+            .unwrap_or(Color([0.0; 3]))
+    }
+}
@@ -0,0 +1,336 @@
+use crate::bsdf::{Bsdf, Microfacet, RoughDielectric};
+use crate::color::Color;
+use crate::integrator::{self, Integrator, SHADOW_BIAS};
+use crate::material::SurfaceType;
+use crate::medium::Medium;
+use crate::object::Object;
+use crate::ray;
+use crate::sampling::{self, SampleRng};
+use crate::scene::Scene;
+use nalgebra::{Point2, Point3};
+use ncollide3d::query::{Ray, RayIntersection};
+use rand::Rng;
+use std::ptr;
+
+/// Bounces before paths may be terminated by russian roulette
+const MIN_BOUNCES: u32 = 3;
+
+/// Unbiased path tracing terminated by russian roulette.
+///
+/// Follows a path from the camera, adding emitted light at every hit and direct light at
+/// every non-specular hit.
+///
+/// Light from emissive objects and lights with an area reaches non-specular hits both through
+/// sampling them and through the next bounce hitting them, both estimates are combined by
+/// multiple importance sampling with the power heuristic.
+///
+/// Inside participating media the path may scatter before reaching the next surface, scattering
+/// points gather direct light through the phase function like surfaces through their BSDF.
+pub struct PathTracer;
+
+impl Integrator for PathTracer {
+    fn radiance(&self, scene: &Scene, mut ray: Ray<f64>, rng: &mut SampleRng) -> Color {
+        let mut radiance = Color([0.0; 3]);
+        let mut throughput = Color([1.0; 3]);
+        // Density, origin and object of the last non-specular bounce, `None` after specular
+        // bounces. Scattering inside a medium has no object.
+        let mut last_bounce: Option<(f64, Point3<f64>, Option<&Object>)> = None;
+        let mut medium = scene.fog.as_ref();
+
+        for bounce in 0.. {
+            let hit = scene.trace(&ray);
+            let max_distance = hit
+                .as_ref()
+                .map_or(f64::INFINITY, |(_, intersection)| intersection.toi);
+            let light_hit = scene.hit_light(&ray, max_distance);
+            let max_distance = light_hit
+                .as_ref()
+                .map_or(max_distance, |(_, distance, _)| *distance);
+
+            if let Some(current) = medium {
+                let (distance, weight) = current.sample_distance(&ray, max_distance, rng);
+                throughput = throughput * weight;
+
+                if let Some(distance) = distance {
+                    let point = ray.point_at(distance);
+                    let (direct, next_ray, pdf) =
+                        Self::scatter_in_medium(scene, current, &ray, &point, rng);
+                    radiance = radiance + throughput * direct;
+                    last_bounce = Some((pdf, point, None));
+                    ray = next_ray;
+
+                    if bounce >= MIN_BOUNCES && !Self::survives(&mut throughput, rng) {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            // Lights with a surface are not part of the objects but absorb the path like them
+            if let Some((light, _, light_radiance)) = light_hit {
+                let weight = last_bounce.map_or(1.0, |(bsdf_pdf, origin, _)| {
+                    sampling::power_heuristic(bsdf_pdf, light.pdf(&origin, &ray.dir))
+                });
+                radiance = radiance + throughput * light_radiance * weight;
+                break;
+            }
+
+            let (object, intersection) = match hit {
+                Some(hit) => hit,
+                None => {
+                    if let Some(environment) = &scene.environment {
+                        let weight = last_bounce.map_or(1.0, |(bsdf_pdf, _, _)| {
+                            sampling::power_heuristic(bsdf_pdf, environment.pdf(&ray.dir))
+                        });
+                        radiance = radiance + throughput * environment.radiance(&ray.dir) * weight;
+                    }
+                    break;
+                }
+            };
+
+            let hit_point = ray.point_at(intersection.toi);
+
+            // Surfaces of media are invisible, the path continues on their other side
+            if object.material.medium.is_some() {
+                medium = scene.medium_behind(object, &ray, &intersection);
+                ray = Ray::new(hit_point - intersection.normal * SHADOW_BIAS, ray.dir);
+                continue;
+            }
+
+            throughput = throughput * object.transmittance(&ray, &intersection);
+
+            if object.material.is_emissive() {
+                let weight = match last_bounce {
+                    // Objects never sample light from themselves
+                    Some((bsdf_pdf, origin, previous))
+                        if previous.map_or(true, |previous| !ptr::eq(previous, object)) =>
+                    {
+                        let light_pdf =
+                            object.pdf_towards(&origin, &hit_point, &intersection.normal);
+                        sampling::power_heuristic(bsdf_pdf, light_pdf)
+                    }
+                    _ => 1.0,
+                };
+                radiance = radiance + throughput * object.material.emission * weight;
+            }
+            let surface_color = object
+                .material
+                .color
+                .color_at(&intersection.uvs.unwrap_or_else(Point2::origin));
+
+            let scatter = match object.material.surface {
+                SurfaceType::Reflective { reflectivity, fuzz }
+                    if rng.gen::<f64>() < reflectivity =>
+                {
+                    let reflection_ray = ray::create_reflection(
+                        intersection.normal,
+                        ray.dir + fuzz * sampling::uniform_sphere(rng),
+                        hit_point,
+                        SHADOW_BIAS,
+                    );
+                    Some((reflection_ray, Color([1.0; 3]), None))
+                }
+                SurfaceType::Diffuse | SurfaceType::Reflective { .. } => {
+                    let bsdf = Bsdf::Lambert {
+                        reflectance: surface_color * object.material.albedo,
+                    };
+                    let (direct, scatter) =
+                        Self::scatter(scene, object, &bsdf, &ray, &intersection, medium, rng);
+                    radiance = radiance + throughput * direct;
+                    scatter
+                }
+                SurfaceType::Microfacet {
+                    metallic,
+                    roughness,
+                } => {
+                    let bsdf =
+                        Bsdf::Microfacet(Microfacet::new(surface_color, metallic, roughness));
+                    let (direct, scatter) =
+                        Self::scatter(scene, object, &bsdf, &ray, &intersection, medium, rng);
+                    radiance = radiance + throughput * direct;
+                    scatter
+                }
+                SurfaceType::Refractive {
+                    transparency,
+                    index,
+                    roughness,
+                    ..
+                } if roughness > 0.0 => {
+                    let bsdf = Bsdf::Dielectric(RoughDielectric::new(
+                        surface_color * transparency,
+                        index,
+                        roughness,
+                        object.is_entering(&ray, &intersection),
+                    ));
+                    let (direct, scatter) =
+                        Self::scatter(scene, object, &bsdf, &ray, &intersection, medium, rng);
+                    radiance = radiance + throughput * direct;
+                    scatter
+                }
+                SurfaceType::Refractive {
+                    transparency,
+                    index,
+                    ..
+                } => {
+                    let kr = integrator::fresnel(ray.dir, intersection.normal, index);
+                    let next_ray = if rng.gen::<f64>() < kr {
+                        Some(ray::create_reflection(
+                            intersection.normal,
+                            ray.dir,
+                            hit_point,
+                            SHADOW_BIAS,
+                        ))
+                    } else {
+                        ray::create_transmission(
+                            intersection.normal,
+                            ray.dir,
+                            hit_point,
+                            SHADOW_BIAS,
+                            index,
+                        )
+                    };
+                    next_ray.map(|next_ray| (next_ray, surface_color * transparency, None))
+                }
+            };
+
+            let (next_ray, weight, pdf) = match scatter {
+                Some(scatter) => scatter,
+                None => break,
+            };
+            // Normals face the incoming ray, transmitted rays leave against them
+            if next_ray.dir.dot(&intersection.normal) < 0.0 {
+                medium = scene.medium_behind(object, &ray, &intersection);
+            }
+            last_bounce = pdf.map(|pdf| (pdf, next_ray.origin, Some(object)));
+            ray = next_ray;
+            throughput = throughput * weight;
+
+            if bounce >= MIN_BOUNCES && !Self::survives(&mut throughput, rng) {
+                break;
+            }
+        }
+
+        radiance
+    }
+}
+
+impl PathTracer {
+    /// Russian roulette, paths with a low throughput are likely terminated and the survivors are
+    /// weighted up to compensate.
+    fn survives(throughput: &mut Color, rng: &mut SampleRng) -> bool {
+        let survival = throughput.max_component().min(0.95);
+        if rng.gen::<f64>() >= survival {
+            return false;
+        }
+        *throughput = *throughput / survival;
+        true
+    }
+
+    /// Estimates the direct light scattered at `point` inside `medium` towards the ray origin
+    /// and samples the direction of the next ray from the phase function. The returned density
+    /// of the direction equals the phase function so the path weight stays unchanged.
+    fn scatter_in_medium(
+        scene: &Scene,
+        medium: &Medium,
+        ray: &Ray<f64>,
+        point: &Point3<f64>,
+        rng: &mut SampleRng,
+    ) -> (Color, Ray<f64>, f64) {
+        let outgoing = -ray.dir;
+
+        let mut light_samples = scene.sample_lights(point, None, Some(medium), rng);
+        light_samples.extend(scene.sample_emitters(None, point, None, Some(medium), rng));
+        let direct = light_samples
+            .into_iter()
+            .map(|sample| {
+                let phase = medium.phase(&outgoing, &sample.direction);
+                match sample.pdf {
+                    Some(pdf) => {
+                        sample.radiance * (phase * sampling::power_heuristic(pdf, phase) / pdf)
+                    }
+                    None => sample.radiance * phase,
+                }
+            })
+            .sum();
+
+        let direction = medium.sample_phase(&outgoing, rng);
+        let pdf = medium.phase(&outgoing, &direction);
+        (direct, Ray::new(*point, direction), pdf)
+    }
+
+    /// Estimates the direct light reflected by a non-specular surface and samples the next
+    /// bounce with its weight and density.
+    ///
+    /// Lights behind transmitting surfaces are not sampled, so their light is only gathered by
+    /// the next bounce which then has to count emission with full weight.
+    fn scatter(
+        scene: &Scene,
+        object: &Object,
+        bsdf: &Bsdf,
+        ray: &Ray<f64>,
+        intersection: &RayIntersection<f64>,
+        medium: Option<&Medium>,
+        rng: &mut SampleRng,
+    ) -> (Color, Option<(Ray<f64>, Color, Option<f64>)>) {
+        let outgoing = -ray.dir;
+        // Shade the side the ray arrived from
+        let normal = if intersection.normal.dot(&outgoing) < 0.0 {
+            -intersection.normal
+        } else {
+            intersection.normal
+        };
+        let hit_point = ray.point_at(intersection.toi);
+        let origin = hit_point + normal * SHADOW_BIAS;
+
+        if bsdf.transmits() {
+            let scatter = bsdf.sample(&outgoing, &normal, rng).and_then(|direction| {
+                let pdf = bsdf.pdf(&outgoing, &direction, &normal);
+                (pdf > 0.0).then(|| {
+                    let weight = bsdf.eval(&outgoing, &direction, &normal)
+                        * (normal.dot(&direction).abs() / pdf);
+                    // Start transmitted rays on the far side of the surface
+                    let side = normal.dot(&direction).signum();
+                    let scatter_ray =
+                        Ray::new(hit_point + normal * (side * SHADOW_BIAS), direction);
+                    (scatter_ray, weight, None)
+                })
+            });
+            return (Color([0.0; 3]), scatter);
+        }
+
+        let mut light_samples = scene.sample_lights(&origin, Some(&normal), medium, rng);
+        light_samples.extend(scene.sample_emitters(
+            Some(object),
+            &origin,
+            Some(&normal),
+            medium,
+            rng,
+        ));
+        let direct = light_samples
+            .into_iter()
+            .map(|sample| {
+                let reflected = bsdf.eval(&outgoing, &sample.direction, &normal)
+                    * sample.radiance
+                    * normal.dot(&sample.direction);
+                match sample.pdf {
+                    Some(pdf) => {
+                        let bsdf_pdf = bsdf.pdf(&outgoing, &sample.direction, &normal);
+                        reflected * (sampling::power_heuristic(pdf, bsdf_pdf) / pdf)
+                    }
+                    None => reflected,
+                }
+            })
+            .sum();
+
+        let scatter = bsdf.sample(&outgoing, &normal, rng).and_then(|direction| {
+            let pdf = bsdf.pdf(&outgoing, &direction, &normal);
+            (pdf > 0.0).then(|| {
+                let weight =
+                    bsdf.eval(&outgoing, &direction, &normal) * (normal.dot(&direction) / pdf);
+                (Ray::new(origin, direction), weight, Some(pdf))
+            })
+        });
+
+        (direct, scatter)
+    }
+}
@@ -0,0 +1,426 @@
+use crate::bsdf::{Bsdf, Microfacet, RoughDielectric};
+use crate::color::Color;
+use crate::integrator::{self, Integrator, SHADOW_BIAS};
+use crate::material::SurfaceType;
+use crate::medium::Medium;
+use crate::object::Object;
+use crate::ray;
+use crate::sampling::{self, SampleRng};
+use crate::scene::Scene;
+use nalgebra::{Point2, Point3, Vector3};
+use ncollide3d::query::{Ray, RayIntersection};
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Recursive ray tracing with shadow rays and one random diffuse bounce per hit, limited by
+/// the `max_recursion_depth` of the scene.
+///
+/// Participating media only attenuate light along every ray, the surfaces bounding volumes are
+/// invisible.
+pub struct Whitted;
+
+impl Integrator for Whitted {
+    fn radiance(&self, scene: &Scene, ray: Ray<f64>, rng: &mut SampleRng) -> Color {
+        self.cast_ray(
+            scene,
+            &ray,
+            scene.fog.as_ref(),
+            scene.max_recursion_depth,
+            true,
+            rng,
+        )
+    }
+}
+
+impl Whitted {
+    fn get_color(
+        &self,
+        scene: &Scene,
+        ray: &Ray<f64>,
+        object: &Object,
+        intersection: &RayIntersection<f64>,
+        medium: Option<&Medium>,
+        depth: u32,
+        rng: &mut SampleRng,
+    ) -> Color {
+        let hit_point = ray.point_at(intersection.toi);
+        // Medium on the other side of the surface for transmitted rays
+        let medium_behind = scene.medium_behind(object, ray, intersection);
+        let surface_color = object
+            .material
+            .color
+            .color_at(&intersection.uvs.unwrap_or_else(Point2::origin));
+
+        match object.material.surface {
+            SurfaceType::Diffuse => self.shade_diffuse(
+                scene,
+                object,
+                surface_color,
+                &hit_point,
+                &intersection.normal,
+                medium,
+                depth,
+                rng,
+            ),
+            SurfaceType::Reflective { reflectivity, fuzz } => {
+                let reflection_ray = ray::create_reflection(
+                    intersection.normal,
+                    ray.dir + fuzz * sampling::uniform_sphere(rng),
+                    hit_point,
+                    SHADOW_BIAS,
+                );
+                let mut color = self.shade_diffuse(
+                    scene,
+                    object,
+                    surface_color,
+                    &hit_point,
+                    &intersection.normal,
+                    medium,
+                    depth,
+                    rng,
+                );
+                color = color * (1.0 - reflectivity);
+                color
+                    + self.cast_ray(scene, &reflection_ray, medium, depth - 1, true, rng)
+                        * reflectivity
+            }
+            SurfaceType::Refractive {
+                transparency,
+                index,
+                roughness,
+                ..
+            } if roughness > 0.0 => {
+                let bsdf = RoughDielectric::new(
+                    surface_color * transparency,
+                    index,
+                    roughness,
+                    object.is_entering(ray, intersection),
+                );
+                self.shade_rough_dielectric(
+                    scene,
+                    &Bsdf::Dielectric(bsdf),
+                    ray,
+                    &hit_point,
+                    &intersection.normal,
+                    (medium, medium_behind),
+                    depth,
+                    rng,
+                )
+            }
+            SurfaceType::Refractive {
+                transparency,
+                index,
+                ..
+            } => {
+                let mut refraction_color = Color([0.0; 3]);
+                let kr = integrator::fresnel(ray.dir, intersection.normal, index);
+                if kr < 1.0 {
+                    let transmission_ray = ray::create_transmission(
+                        intersection.normal,
+                        ray.dir,
+                        hit_point,
+                        SHADOW_BIAS,
+                        index,
+                    )
+                    .unwrap();
+
+                    refraction_color = self.cast_ray(
+                        scene,
+                        &transmission_ray,
+                        medium_behind,
+                        depth - 1,
+                        true,
+                        rng,
+                    );
+                }
+
+                let reflection_ray =
+                    ray::create_reflection(intersection.normal, ray.dir, hit_point, SHADOW_BIAS);
+                let reflection_color =
+                    self.cast_ray(scene, &reflection_ray, medium, depth - 1, true, rng);
+
+                (reflection_color * kr + refraction_color * (1.0 - kr))
+                    * transparency
+                    * surface_color
+            }
+            SurfaceType::Microfacet {
+                metallic,
+                roughness,
+            } => self.shade_microfacet(
+                scene,
+                object,
+                &Bsdf::Microfacet(Microfacet::new(surface_color, metallic, roughness)),
+                ray,
+                &hit_point,
+                &intersection.normal,
+                medium,
+                depth,
+                rng,
+            ),
+        }
+    }
+
+    /// Follows one sampled reflection or transmission through a frosted surface, `media` are
+    /// the media in front of and behind the surface.
+    fn shade_rough_dielectric(
+        &self,
+        scene: &Scene,
+        bsdf: &Bsdf,
+        ray: &Ray<f64>,
+        hit_point: &Point3<f64>,
+        surface_normal: &Vector3<f64>,
+        media: (Option<&Medium>, Option<&Medium>),
+        depth: u32,
+        rng: &mut SampleRng,
+    ) -> Color {
+        let outgoing = -ray.dir;
+        let normal = if surface_normal.dot(&outgoing) < 0.0 {
+            -surface_normal
+        } else {
+            *surface_normal
+        };
+
+        bsdf.sample(&outgoing, &normal, rng)
+            .map(|direction| {
+                let pdf = bsdf.pdf(&outgoing, &direction, &normal);
+                if pdf <= 0.0 {
+                    return Color([0.0; 3]);
+                }
+
+                let cos = normal.dot(&direction);
+                let scatter_ray =
+                    Ray::new(hit_point + normal * (cos.signum() * SHADOW_BIAS), direction);
+                let medium = if cos < 0.0 { media.1 } else { media.0 };
+                bsdf.eval(&outgoing, &direction, &normal)
+                    * self.cast_ray(scene, &scatter_ray, medium, depth - 1, true, rng)
+                    * (cos.abs() / pdf)
+            })
+            .unwrap_or(Color([0.0; 3]))
+    }
+
+    fn shade_microfacet(
+        &self,
+        scene: &Scene,
+        object: &Object,
+        bsdf: &Bsdf,
+        ray: &Ray<f64>,
+        hit_point: &Point3<f64>,
+        surface_normal: &Vector3<f64>,
+        medium: Option<&Medium>,
+        depth: u32,
+        rng: &mut SampleRng,
+    ) -> Color {
+        let outgoing = -ray.dir;
+        let normal = if surface_normal.dot(&outgoing) < 0.0 {
+            -surface_normal
+        } else {
+            *surface_normal
+        };
+        let origin = hit_point + normal * SHADOW_BIAS;
+
+        let light_color = scene
+            .lights
+            .iter()
+            .filter_map(|light| {
+                let light_point = light.sample(hit_point, rng)?;
+                let direction = light_point.direction;
+                let transmittance = scene.transmittance(
+                    &Ray::new(origin, direction),
+                    light_point.distance,
+                    medium,
+                    rng,
+                )?;
+
+                Some(
+                    bsdf.eval(&outgoing, &direction, &normal)
+                        * light_point.irradiance
+                        * transmittance
+                        * normal.dot(&direction).max(0.0),
+                )
+            })
+            .sum::<Color>();
+
+        let emitted_color = scene
+            .sample_emitters(Some(object), &origin, Some(&normal), medium, rng)
+            .into_iter()
+            .map(|sample| {
+                bsdf.eval(&outgoing, &sample.direction, &normal)
+                    * sample.radiance
+                    * (normal.dot(&sample.direction) / sample.pdf.unwrap_or(1.0))
+            })
+            .sum::<Color>();
+
+        // Emitters hit by the scatter ray are already sampled directly above
+        let scatter_color = bsdf
+            .sample(&outgoing, &normal, rng)
+            .map(|direction| {
+                let pdf = bsdf.pdf(&outgoing, &direction, &normal);
+                let scatter_ray = Ray::new(origin, direction);
+                bsdf.eval(&outgoing, &direction, &normal)
+                    * self.cast_ray(scene, &scatter_ray, medium, depth - 1, false, rng)
+                    * (normal.dot(&direction) / pdf)
+            })
+            .unwrap_or(Color([0.0; 3]));
+
+        light_color + emitted_color + scatter_color
+    }
+
+    fn shade_diffuse(
+        &self,
+        scene: &Scene,
+        object: &Object,
+        surface_color: Color,
+        hit_point: &Point3<f64>,
+        surface_normal: &Vector3<f64>,
+        medium: Option<&Medium>,
+        depth: u32,
+        rng: &mut SampleRng,
+    ) -> Color {
+        let origin = hit_point + surface_normal * SHADOW_BIAS;
+        let light_reflected = object.material.albedo / PI;
+
+        let scatter_color = {
+            let scatter_ray = Ray::new(
+                origin,
+                ((hit_point + surface_normal + rng.gen::<Vector3<f64>>().normalize()) - origin)
+                    .normalize(),
+            );
+
+            // Emitters hit by the scatter ray are already sampled directly below
+            surface_color
+                * self.cast_ray(scene, &scatter_ray, medium, depth - 1, false, rng)
+                * surface_normal.dot(&scatter_ray.dir).max(0.0)
+                * light_reflected
+        };
+
+        // Ad hoc normalization keeping scenes with many lights from overexposing
+        let light_normalization = 1.0 + scene.lights.len() as f64;
+
+        scene
+            .lights
+            .iter()
+            .map(|light| {
+                let light_point = match light.sample(hit_point, rng) {
+                    Some(light_point) => light_point,
+                    None => return Color([0.0; 3]),
+                };
+                let direction_to_light = light_point.direction;
+                let shadow_ray = Ray::new(origin, direction_to_light);
+                let transmittance =
+                    scene.transmittance(&shadow_ray, light_point.distance, medium, rng);
+                let light_color = match transmittance {
+                    Some(transmittance) => light_point.irradiance * transmittance,
+                    None => match Self::trace_opaque(scene, &shadow_ray) {
+                        Some((object, _))
+                            if matches!(
+                                object.material.surface,
+                                SurfaceType::Refractive { .. }
+                            ) =>
+                        {
+                            light.color()
+                                + self.cast_ray(scene, &shadow_ray, medium, depth - 1, true, rng)
+                        }
+                        _ => [0.1; 3].into(),
+                    },
+                };
+
+                let light_power = surface_normal.dot(&direction_to_light).max(0.0);
+
+                surface_color * light_color * light_power * light_reflected
+            })
+            .sum::<Color>()
+            / light_normalization
+            + scene
+                .sample_emitters(Some(object), &origin, Some(surface_normal), medium, rng)
+                .into_iter()
+                .map(|sample| {
+                    surface_color
+                        * sample.radiance
+                        * light_reflected
+                        * (surface_normal.dot(&sample.direction) / sample.pdf.unwrap_or(1.0))
+                })
+                .sum::<Color>()
+            + scatter_color
+    }
+
+    /// Traces a ray through the invisible surfaces of media to the first opaque hit, its time of
+    /// impact is the distance along `ray`.
+    fn trace_opaque<'a>(
+        scene: &'a Scene,
+        ray: &Ray<f64>,
+    ) -> Option<(&'a Object, RayIntersection<f64>)> {
+        let mut through_ray = *ray;
+        let mut distance = 0.0;
+
+        loop {
+            let (object, mut intersection) = scene.trace(&through_ray)?;
+            distance += intersection.toi;
+            if object.material.medium.is_none() {
+                intersection.toi = distance;
+                return Some((object, intersection));
+            }
+
+            through_ray = Ray::new(
+                through_ray.point_at(intersection.toi) - intersection.normal * SHADOW_BIAS,
+                ray.dir,
+            );
+        }
+    }
+
+    /// Light arriving along `ray` which starts inside `medium`.
+    fn cast_ray(
+        &self,
+        scene: &Scene,
+        ray: &Ray<f64>,
+        medium: Option<&Medium>,
+        depth: u32,
+        with_emission: bool,
+        rng: &mut SampleRng,
+    ) -> Color {
+        if depth == 0 {
+            return Color([0.0; 3]);
+        }
+
+        let hit = scene.trace(ray);
+        let max_distance = hit
+            .as_ref()
+            .map_or(f64::INFINITY, |(_, intersection)| intersection.toi);
+        // Lights with a surface are seen like emitters and sampled directly like them
+        if let Some((_, distance, radiance)) = scene.hit_light(ray, max_distance) {
+            if !with_emission {
+                return Color([0.0; 3]);
+            }
+            return radiance
+                * scene
+                    .transmittance(ray, distance, medium, rng)
+                    .unwrap_or(Color([0.0; 3]));
+        }
+
+        let (object, intersection) = match hit {
+            Some(hit) => hit,
+            None => return scene.background(&ray.dir),
+        };
+        let attenuation = scene
+            .transmittance(ray, intersection.toi, medium, rng)
+            .unwrap_or(Color([0.0; 3]));
+
+        if object.material.medium.is_some() {
+            let through_ray = Ray::new(
+                ray.point_at(intersection.toi) - intersection.normal * SHADOW_BIAS,
+                ray.dir,
+            );
+            let medium = scene.medium_behind(object, ray, &intersection);
+            return self.cast_ray(scene, &through_ray, medium, depth, with_emission, rng)
+                * attenuation;
+        }
+
+        let color = self.get_color(scene, ray, object, &intersection, medium, depth, rng);
+        // Emitters that cannot be sampled directly only light the scene when hit
+        let color = if with_emission || !object.is_sampleable() {
+            color + object.material.emission
+        } else {
+            color
+        };
+        color * object.transmittance(ray, &intersection) * attenuation
+    }
+}
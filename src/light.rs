@@ -1,5 +1,8 @@
 use crate::color::Color;
+use crate::sampling::{self, SampleRng};
 use nalgebra::{Point3, Vector3};
+use ncollide3d::query::Ray;
+use rand::Rng;
 use std::f64::consts::PI;
 
 pub struct DirectionalLight {
@@ -10,47 +13,308 @@ pub struct DirectionalLight {
 
 pub struct SphericalLight {
     pub position: Point3<f64>,
+    /// Radius of the glowing ball, zero for a point light with hard shadows
+    pub radius: f64,
     pub color: Color,
     pub intensity: f64,
 }
 
+impl SphericalLight {
+    /// Cosine of the half angle of the cone the ball covers as seen from `point`, `None` for
+    /// point lights or if `point` is inside the ball.
+    fn cos_max(&self, point: &Point3<f64>) -> Option<f64> {
+        let distance_squared = (self.position - point).norm_squared();
+        let radius_squared = self.radius * self.radius;
+        (self.radius > 0.0 && distance_squared > radius_squared)
+            .then(|| (1.0 - radius_squared / distance_squared).max(0.0).sqrt())
+    }
+
+    /// Light leaving every point of the surface of the ball.
+    fn radiance(&self) -> Color {
+        self.color * self.intensity / (4.0 * PI * self.radius * self.radius * PI)
+    }
+}
+
+pub struct SpotLight {
+    pub position: Point3<f64>,
+    pub direction: Vector3<f64>,
+    pub color: Color,
+    pub intensity: f64,
+    /// Angle from the direction in radians up to which the light has its full intensity
+    pub inner_angle: f64,
+    /// Angle from the direction in radians beyond which the light is dark
+    pub outer_angle: f64,
+}
+
+impl SpotLight {
+    /// Smoothly fades the light from the inner to the outer cone.
+    fn falloff(&self, point: &Point3<f64>) -> f64 {
+        let cos = (point - self.position).normalize().dot(&self.direction);
+        let cos_inner = self.inner_angle.cos();
+        let cos_outer = self.outer_angle.cos();
+
+        if cos >= cos_inner {
+            1.0
+        } else if cos <= cos_outer {
+            0.0
+        } else {
+            let t = (cos - cos_outer) / (cos_inner - cos_outer);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+}
+
+/// Rectangle shining to the side its normal points to. The width runs horizontally, along the
+/// x axis for rectangles facing up or down.
+pub struct RectangleLight {
+    pub position: Point3<f64>,
+    pub normal: Vector3<f64>,
+    pub width: f64,
+    pub height: f64,
+    pub color: Color,
+    pub intensity: f64,
+}
+
+impl RectangleLight {
+    /// Whether the point at `offset` from the center on the plane of the light lies inside it.
+    fn contains(&self, offset: &Vector3<f64>) -> bool {
+        let (width_axis, height_axis) = self.axes();
+        offset.dot(&width_axis).abs() <= self.width / 2.0
+            && offset.dot(&height_axis).abs() <= self.height / 2.0
+    }
+
+    fn axes(&self) -> (Vector3<f64>, Vector3<f64>) {
+        let width_axis = if self.normal.y.abs() > 0.999 {
+            Vector3::x()
+        } else {
+            Vector3::y().cross(&self.normal).normalize()
+        };
+        (width_axis, self.normal.cross(&width_axis))
+    }
+}
+
+/// Disk shining to the side its normal points to.
+pub struct DiskLight {
+    pub position: Point3<f64>,
+    pub normal: Vector3<f64>,
+    pub radius: f64,
+    pub color: Color,
+    pub intensity: f64,
+}
+
+impl DiskLight {
+    /// Whether the point at `offset` from the center on the plane of the light lies inside it.
+    fn contains(&self, offset: &Vector3<f64>) -> bool {
+        offset.norm_squared() <= self.radius * self.radius
+    }
+}
+
 pub enum Light {
     Directional(DirectionalLight),
     Spherical(SphericalLight),
+    Spot(SpotLight),
+    Rectangle(RectangleLight),
+    Disk(DiskLight),
+}
+
+/// Sampled point on a light as seen from a point in the scene.
+pub struct LightPoint {
+    pub direction: Vector3<f64>,
+    pub distance: f64,
+    /// Light arriving from the sampled point divided by the density of the direction, an
+    /// estimate of the irradiance on a surface facing the light
+    pub irradiance: Color,
+    /// Density of the direction with respect to solid angle, `None` for lights which rays
+    /// cannot hit and can only be reached by sampling them
+    pub pdf: Option<f64>,
 }
 
 impl Light {
-    pub fn direction_to_light(&self, hit_point: &Point3<f64>) -> Vector3<f64> {
+    /// Samples a point on the light uniformly with respect to the solid angle it covers or its
+    /// area, `None` if `point` is behind the light.
+    pub fn sample(&self, point: &Point3<f64>, rng: &mut SampleRng) -> Option<LightPoint> {
         match self {
-            Light::Directional(directional) => -directional.direction.clone(),
+            Light::Directional(directional) => Some(LightPoint {
+                direction: -directional.direction,
+                distance: f64::INFINITY,
+                irradiance: directional.color * directional.intensity,
+                pdf: None,
+            }),
             Light::Spherical(spherical) => {
-                ((&spherical.position + Vector3::new_random().normalize() * 0.2) - hit_point)
-                    .normalize()
+                let to_center = spherical.position - point;
+                let cos_max = match spherical.cos_max(point) {
+                    Some(cos_max) => cos_max,
+                    None => {
+                        return Some(point_light(
+                            to_center,
+                            spherical.color * spherical.intensity,
+                        ))
+                    }
+                };
+
+                let distance = to_center.norm();
+                let direction = sampling::uniform_cone(&(to_center / distance), cos_max, rng);
+
+                // Closest intersection of the sampled direction with the ball
+                let b = -to_center.dot(&direction);
+                let discriminant =
+                    b * b - (distance * distance - spherical.radius * spherical.radius);
+                let pdf = 1.0 / (2.0 * PI * (1.0 - cos_max));
+                Some(LightPoint {
+                    direction,
+                    distance: -b - discriminant.max(0.0).sqrt(),
+                    irradiance: spherical.radiance() / pdf,
+                    pdf: Some(pdf),
+                })
+            }
+            Light::Spot(spot) => Some(point_light(
+                spot.position - point,
+                spot.color * (spot.intensity * spot.falloff(point)),
+            )),
+            Light::Rectangle(rectangle) => {
+                let (width_axis, height_axis) = rectangle.axes();
+                let light_point = rectangle.position
+                    + width_axis * ((rng.gen::<f64>() - 0.5) * rectangle.width)
+                    + height_axis * ((rng.gen::<f64>() - 0.5) * rectangle.height);
+                area_light(
+                    point,
+                    &light_point,
+                    &rectangle.normal,
+                    rectangle.color * rectangle.intensity,
+                    rectangle.width * rectangle.height,
+                )
+            }
+            Light::Disk(disk) => {
+                let (tangent, bitangent) = sampling::orthonormal_basis(&disk.normal);
+                let offset = sampling::uniform_disk(rng) * disk.radius;
+                let light_point = disk.position + tangent * offset.x + bitangent * offset.y;
+                area_light(
+                    point,
+                    &light_point,
+                    &disk.normal,
+                    disk.color * disk.intensity,
+                    PI * disk.radius * disk.radius,
+                )
             }
         }
     }
 
-    pub fn intensity(&self, hit_point: &Point3<f64>) -> f64 {
+    /// Distance along `ray` to the surface of the light and the radiance leaving it, `None` if
+    /// the ray misses it or the light has no surface rays can hit.
+    pub fn hit(&self, ray: &Ray<f64>) -> Option<(f64, Color)> {
         match self {
-            Light::Directional(directional) => directional.intensity,
             Light::Spherical(spherical) => {
-                let r2 = (&spherical.position - hit_point).norm();
-                spherical.intensity / (4.0 * PI * r2)
+                spherical.cos_max(&ray.origin)?;
+
+                let to_center = spherical.position - ray.origin;
+                let b = to_center.dot(&ray.dir);
+                let discriminant =
+                    b * b - (to_center.norm_squared() - spherical.radius * spherical.radius);
+                (b > 0.0 && discriminant >= 0.0)
+                    .then(|| (b - discriminant.sqrt(), spherical.radiance()))
+            }
+            Light::Rectangle(rectangle) => {
+                let (distance, _) = self.hit_plane(ray)?;
+                let area = rectangle.width * rectangle.height;
+                Some((
+                    distance,
+                    rectangle.color * rectangle.intensity / (PI * area),
+                ))
+            }
+            Light::Disk(disk) => {
+                let (distance, _) = self.hit_plane(ray)?;
+                let area = PI * disk.radius * disk.radius;
+                Some((distance, disk.color * disk.intensity / (PI * area)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Density of `sample` at `point` with respect to solid angle for `direction`, zero for
+    /// lights which rays cannot hit and directions missing the light.
+    pub fn pdf(&self, point: &Point3<f64>, direction: &Vector3<f64>) -> f64 {
+        let area = match self {
+            Light::Spherical(spherical) => {
+                return spherical
+                    .cos_max(point)
+                    .map_or(0.0, |cos_max| 1.0 / (2.0 * PI * (1.0 - cos_max)))
             }
+            Light::Rectangle(rectangle) => rectangle.width * rectangle.height,
+            Light::Disk(disk) => PI * disk.radius * disk.radius,
+            _ => return 0.0,
+        };
+
+        self.hit_plane(&Ray::new(*point, *direction))
+            .map_or(0.0, |(distance, cos_light)| {
+                distance * distance / (cos_light * area)
+            })
+    }
+
+    /// Distance along `ray` to the front of a rectangle or disk light and the cosine between the
+    /// ray and the normal of the light.
+    fn hit_plane(&self, ray: &Ray<f64>) -> Option<(f64, f64)> {
+        let (position, normal) = match self {
+            Light::Rectangle(rectangle) => (rectangle.position, rectangle.normal),
+            Light::Disk(disk) => (disk.position, disk.normal),
+            _ => return None,
+        };
+
+        let cos_light = -normal.dot(&ray.dir);
+        let distance = (ray.origin - position).dot(&normal) / cos_light;
+        if cos_light <= 0.0 || distance <= 0.0 {
+            return None;
         }
+
+        let offset = ray.point_at(distance) - position;
+        let inside = match self {
+            Light::Rectangle(rectangle) => rectangle.contains(&offset),
+            Light::Disk(disk) => disk.contains(&offset),
+            _ => false,
+        };
+        inside.then(|| (distance, cos_light))
     }
 
     pub fn color(&self) -> Color {
         match self {
             Light::Directional(directional) => directional.color,
             Light::Spherical(spherical) => spherical.color,
+            Light::Spot(spot) => spot.color,
+            Light::Rectangle(rectangle) => rectangle.color,
+            Light::Disk(disk) => disk.color,
         }
     }
+}
 
-    pub fn distance_to(&self, point: &Point3<f64>) -> f64 {
-        match self {
-            Light::Directional(_) => f64::INFINITY,
-            Light::Spherical(spherical) => (spherical.position - point).norm(),
-        }
+/// Light of a point with the given power, falling off with the squared distance.
+fn point_light(to_light: Vector3<f64>, power: Color) -> LightPoint {
+    let distance = to_light.norm();
+    LightPoint {
+        direction: to_light / distance,
+        distance,
+        irradiance: power / (4.0 * PI * distance * distance),
+        pdf: None,
     }
 }
+
+/// Light of a point sampled uniformly on the area of a one sided light with the given power.
+/// The radiance of the light divided by the density of the direction is its power times the
+/// cosine at the light divided by pi and the squared distance, independent of the area.
+fn area_light(
+    point: &Point3<f64>,
+    light_point: &Point3<f64>,
+    normal: &Vector3<f64>,
+    power: Color,
+    area: f64,
+) -> Option<LightPoint> {
+    let to_light = light_point - point;
+    let distance = to_light.norm();
+    let direction = to_light / distance;
+    let cos_light = -normal.dot(&direction);
+
+    (cos_light > 0.0).then(|| LightPoint {
+        direction,
+        distance,
+        irradiance: power * (cos_light / (PI * distance * distance)),
+        pdf: Some(distance * distance / (cos_light * area)),
+    })
+}
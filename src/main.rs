@@ -1,134 +1,207 @@
 #![feature(bool_to_option)]
 #![feature(clamp)]
 
+mod bench;
+mod bsdf;
+mod bvh;
+mod camera;
 mod color;
+mod environment;
+mod integrator;
 mod light;
 mod material;
+mod medium;
+mod mesh;
 mod object;
+mod output;
+mod preview;
 mod ray;
+mod sampling;
 mod scene;
+mod scene_file;
+mod sky;
+mod texture;
+mod tone_mapping;
+mod volume;
 
-use crate::light::{DirectionalLight, Light, SphericalLight};
-use crate::material::SurfaceType;
-use crate::object::ObjectBuilder;
-use crate::scene::Scene;
-use glutin_window::GlutinWindow as Window;
-use image::{DynamicImage, ImageBuffer, Rgb};
-use nalgebra::{Perspective3, Point3, Vector3};
-use ncollide3d::shape;
-use opengl_graphics::{GlGraphics, OpenGL, Texture, TextureSettings};
-use piston::event_loop::{EventLoop, EventSettings, Events};
-use piston::input::RenderEvent;
-use piston::window::WindowSettings;
-
-pub const PIXEL_WIDTH: u32 = 800;
-pub const PIXEL_HEIGHT: u32 = 600;
+use crate::tone_mapping::Operator;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use std::process;
 
 fn main() {
-    let opengl = OpenGL::V4_5;
-
-    let mut window: Window = WindowSettings::new("Ray Tracer", [PIXEL_WIDTH, PIXEL_HEIGHT])
-        .graphics_api(opengl)
-        .exit_on_esc(true)
-        .build()
-        .unwrap();
-
-    let mut events = Events::new(EventSettings::new().lazy(true));
-    let mut gl = GlGraphics::new(opengl);
-
-    let scene = Scene {
-        perspective: Perspective3::new(
-            PIXEL_WIDTH as f64 / PIXEL_HEIGHT as f64,
-            90.0_f64.to_radians(),
-            1.0,
-            1000.0,
+    let matches = App::new("Ray Tracer")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("Renders a scene file to an image")
+                .arg(
+                    Arg::with_name("scene")
+                        .help("Scene description file")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .default_value("image.png")
+                        .help("Path of the rendered image, .exr and .hdr keep the full range"),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .long("width")
+                        .takes_value(true)
+                        .help("Image width in pixels, overrides width of the scene"),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .takes_value(true)
+                        .help("Image height in pixels, overrides height of the scene"),
+                )
+                .arg(
+                    Arg::with_name("spp")
+                        .long("spp")
+                        .takes_value(true)
+                        .help("Rays per pixel, overrides max_rays of the scene"),
+                )
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .help(
+                            "Maximum recursion depth, overrides max_recursion_depth of the scene",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("integrator")
+                        .long("integrator")
+                        .takes_value(true)
+                        .possible_values(&integrator::NAMES)
+                        .help("Rendering algorithm, overrides integrator of the scene"),
+                )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .help("Seed of the random numbers, overrides seed of the scene"),
+                )
+                .arg(
+                    Arg::with_name("tone_map")
+                        .long("tone-map")
+                        .takes_value(true)
+                        .possible_values(&tone_mapping::NAMES)
+                        .help("Tone mapping operator, overrides operator of the scene"),
+                )
+                .arg(
+                    Arg::with_name("exposure")
+                        .long("exposure")
+                        .takes_value(true)
+                        .allow_hyphen_values(true)
+                        .help("Exposure in stops, overrides exposure of the scene"),
+                )
+                .arg(
+                    Arg::with_name("preview")
+                        .long("preview")
+                        .help("Shows the rendered image in a window"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Compares ray tracing with and without the bounding volume hierarchy")
+                .arg(
+                    Arg::with_name("spheres")
+                        .long("spheres")
+                        .takes_value(true)
+                        .default_value("10000")
+                        .help("Number of randomly placed spheres"),
+                )
+                .arg(
+                    Arg::with_name("rays")
+                        .long("rays")
+                        .takes_value(true)
+                        .default_value("100000")
+                        .help("Number of traced rays"),
+                ),
+        )
+        .get_matches();
+
+    match matches.subcommand() {
+        ("render", Some(matches)) => render(matches),
+        ("bench", Some(matches)) => bench::run(
+            parse_value(matches, "spheres").unwrap() as usize,
+            parse_value(matches, "rays").unwrap() as usize,
         ),
-        max_recursion_depth: 5,
-        max_rays: 20,
-        lights: vec![
-            /*Light::Directional(DirectionalLight {
-                direction: Vector3::new(0.0, -1.0, 0.0).normalize(),
-                color: [1.0; 3].into(),
-                intensity: 5.0,
-            }),*/
-            Light::Spherical(SphericalLight {
-                position: Point3::new(0.0, 3.7, -5.0),
-                color: [1.0; 3].into(),
-                intensity: 300.0,
-            }),
-        ],
-        objects: vec![
-            ObjectBuilder::new(shape::Ball::new(1.0))
-                .position(-2.5, -0.5, -4.0)
-                .color([1.0, 0.0, 0.0])
-                .build(),
-            ObjectBuilder::new(shape::Ball::new(1.5))
-                .position(2.0, 0.0, -5.0)
-                .color([1.0, 1.0, 1.0])
-                .surface(SurfaceType::Refractive {
-                    transparency: 0.9,
-                    index: 1.5,
-                })
-                .build(),
-            ObjectBuilder::new(shape::Ball::new(0.5))
-                .position(0.0, -1.0, -4.3)
-                .color([1.0, 1.0, 0.0])
-                .surface(SurfaceType::Reflective {
-                    reflectivity: 0.4,
-                    fuzz: 0.3,
-                })
-                .build(),
-            ObjectBuilder::new(shape::Cuboid::new(Vector3::new(0.5, 1.5, 0.1)))
-                .position(-1.5, -1.4, -3.0)
-                .rotation(*Vector3::y_axis(), 20.0)
-                .surface(SurfaceType::Refractive {
-                    transparency: 0.9,
-                    index: 1.5,
-                })
-                .build(),
-            ObjectBuilder::new(shape::Plane::new(-Vector3::y_axis()))
-                .position(0.0, -1.5, 0.0)
-                .surface(SurfaceType::Diffuse)
-                .color([0.73, 0.1, 0.73])
-                .build(),
-            ObjectBuilder::new(shape::Plane::new(Vector3::y_axis()))
-                .position(0.0, 4.0, 0.0)
-                .surface(SurfaceType::Diffuse)
-                .color([0.1, 0.73, 0.73])
-                .build(),
-            ObjectBuilder::new(shape::Plane::new(-Vector3::x_axis()))
-                .position(-4.0, 0.0, 0.0)
-                .surface(SurfaceType::Diffuse)
-                .color([0.65, 0.05, 0.05])
-                .build(),
-            ObjectBuilder::new(shape::Plane::new(Vector3::x_axis()))
-                .position(4.0, 0.0, 0.0)
-                .surface(SurfaceType::Diffuse)
-                .color([0.12, 0.45, 0.15])
-                .build(),
-            ObjectBuilder::new(shape::Plane::new(-Vector3::z_axis()))
-                .position(0.0, 0.0, -7.0)
-                .surface(SurfaceType::Diffuse)
-                .color([0.1, 0.1, 0.73])
-                .build(),
-            ObjectBuilder::new(shape::Plane::new(Vector3::z_axis()))
-                .position(0.0, 0.0, 1.0)
-                .surface(SurfaceType::Diffuse)
-                .color([0.73, 0.73, 0.73])
-                .build(),
-        ],
-    };
-
-    let texture_settings = TextureSettings::new();
-    let image = scene.create_image();
-    image.save("image.png").unwrap();
-    let texture = Texture::from_image(&image, &texture_settings);
-
-    while let Some(event) = events.next(&mut window) {
-        if let Some(render_args) = event.render_args() {
-            gl.draw(render_args.viewport(), |c, gl| {
-                graphics::image(&texture, c.transform, gl);
-            });
-        }
+        _ => {}
+    }
+}
+
+fn render(matches: &ArgMatches) {
+    let scene_path = matches.value_of("scene").unwrap();
+    let mut scene = scene_file::load(scene_path).unwrap_or_else(|error| {
+        eprintln!("{}: {}", scene_path, error);
+        process::exit(1);
+    });
+
+    if let Some(width) = parse_value(matches, "width") {
+        scene.width = width;
+    }
+
+    if let Some(height) = parse_value(matches, "height") {
+        scene.height = height;
+    }
+
+    if let Some(spp) = parse_value(matches, "spp") {
+        scene.max_rays = spp;
+    }
+
+    if let Some(depth) = parse_value(matches, "depth") {
+        scene.max_recursion_depth = depth;
+    }
+
+    if let Some(name) = matches.value_of("integrator") {
+        scene.integrator = integrator::by_name(name).unwrap();
+    }
+
+    if let Some(seed) = matches.value_of("seed") {
+        scene.seed = seed.parse().unwrap_or_else(|_| {
+            eprintln!("--seed expects a number, got '{}'", seed);
+            process::exit(1);
+        });
+    }
+
+    if let Some(name) = matches.value_of("tone_map") {
+        scene.tone_mapping.operator = Operator::from_name(name).unwrap();
+    }
+
+    if let Some(exposure) = matches.value_of("exposure") {
+        scene.tone_mapping.exposure = exposure.parse().unwrap_or_else(|_| {
+            eprintln!("--exposure expects a number, got '{}'", exposure);
+            process::exit(1);
+        });
+    }
+
+    let framebuffer = scene.create_image();
+    let output = matches.value_of("output").unwrap();
+    output::save(&framebuffer, &scene.tone_mapping, output).unwrap_or_else(|error| {
+        eprintln!("{}: could not save image: {}", output, error);
+        process::exit(1);
+    });
+
+    if matches.is_present("preview") {
+        preview::show(&output::to_rgba(&framebuffer, &scene.tone_mapping));
     }
 }
+
+fn parse_value(matches: &ArgMatches, name: &str) -> Option<u32> {
+    matches.value_of(name).map(|value| {
+        value
+            .parse()
+            .ok()
+            .filter(|&value| value > 0)
+            .unwrap_or_else(|| {
+                eprintln!("--{} expects a positive number, got '{}'", name, value);
+                process::exit(1);
+            })
+    })
+}
@@ -1,58 +1,83 @@
 use crate::color::Color;
+use crate::medium::Medium;
+use crate::texture::{self, Texture};
+use nalgebra::Point2;
+use serde::Deserialize;
 
 pub struct Material {
-    pub color: Color,
+    pub color: Coloration,
     pub albedo: f64,
     pub surface: SurfaceType,
+    /// Radiance emitted by the surface
+    pub emission: Color,
+    /// Medium filling the object, its surface then only bounds the medium and is invisible
+    pub medium: Option<Medium>,
 }
 
-pub enum SurfaceType {
-    Diffuse,
-    Reflective { reflectivity: f64, fuzz: f64 },
-    Refractive { index: f64, transparency: f64 },
+impl Material {
+    pub fn is_emissive(&self) -> bool {
+        self.emission.0.iter().any(|&channel| channel > 0.0)
+    }
 }
 
-/*
-use crate::color::Color;
-use image::{DynamicImage, GenericImageView};
-use std::ops::{Add, Mul};
-
-pub struct Material {
-    pub color: Coloration,
-    pub albedo: f64,
-    pub surface: SurfaceType,
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SurfaceType {
+    Diffuse,
+    Reflective {
+        reflectivity: f64,
+        fuzz: f64,
+    },
+    Refractive {
+        index: f64,
+        transparency: f64,
+        /// Microfacet roughness for frosted glass, 0 for smooth glass
+        #[serde(default)]
+        roughness: f64,
+        /// Beer-Lambert absorption coefficient of each color channel per unit of distance,
+        /// light traveling the distance d inside keeps exp(-absorption * d) of its intensity
+        #[serde(default)]
+        absorption: [f64; 3],
+    },
+    /// glTF metallic/roughness material using the color as base color, ignores the albedo
+    Microfacet {
+        metallic: f64,
+        roughness: f64,
+    },
 }
 
-
+#[derive(Clone)]
 pub enum Coloration {
     Color(Color),
-    Texture(DynamicImage),
+    Texture { texture: Texture, scale: f64 },
+    Checker { even: Color, odd: Color, scale: f64 },
+    Noise { low: Color, high: Color, scale: f64 },
 }
 
 impl Coloration {
-    pub fn color_at(&self, texture_coords: &TextureCoords) -> Color {
+    pub fn color_at(&self, texture_coords: &Point2<f64>) -> Color {
         match self {
             Coloration::Color(color) => *color,
-            Coloration::Texture(texture) => {
-                let tex_x = Self::wrap(texture_coords.x, texture.width());
-                let tex_y = Self::wrap(texture_coords.y, texture.height());
-
-                let [r, g, b, _] = texture.get_pixel(tex_x, tex_y).0;
-                Color([r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0])
+            Coloration::Texture { texture, scale } => texture.color_at(&(texture_coords * *scale)),
+            Coloration::Checker { even, odd, scale } => {
+                texture::checker(&(texture_coords * *scale), *even, *odd)
+            }
+            Coloration::Noise { low, high, scale } => {
+                let t = texture::noise(&(texture_coords * *scale));
+                *low * (1.0 - t) + *high * t
             }
         }
     }
+}
 
-    fn wrap(val: f64, bound: u32) -> u32 {
-        let signed_bound = bound as i32;
-        let float_coord = val * bound as f64;
-        let wrapped_coord = (float_coord as i32) % signed_bound;
+impl From<Color> for Coloration {
+    fn from(color: Color) -> Self {
+        Coloration::Color(color)
+    }
+}
 
-        if wrapped_coord < 0 {
-            (wrapped_coord + signed_bound) as u32
-        } else {
-            wrapped_coord as u32
-        }
+impl From<[f64; 3]> for Coloration {
+    fn from(color: [f64; 3]) -> Self {
+        Coloration::Color(color.into())
     }
 }
-*/
@@ -0,0 +1,184 @@
+use crate::color::Color;
+use crate::sampling::{self, SampleRng};
+use crate::volume::VoxelGrid;
+use nalgebra::{Isometry3, Vector3};
+use ncollide3d::query::Ray;
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Participating medium like fog or smoke. Coefficients are per unit of distance.
+#[derive(Clone)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    /// Henyey-Greenstein asymmetry between -1 (backward) and 1 (forward scattering)
+    pub asymmetry: f64,
+    /// Scales the coefficients at every point, the medium is homogeneous without it
+    pub density: Option<VoxelGrid>,
+}
+
+impl Medium {
+    /// Moves the density grid along with the object the medium fills.
+    pub fn placed(mut self, isometry: Isometry3<f64>) -> Self {
+        if let Some(grid) = &mut self.density {
+            grid.isometry = isometry;
+        }
+        self
+    }
+
+    fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    /// Fraction of light passing `distance` along the ray through the medium without
+    /// interacting. Estimated by ratio tracking in heterogeneous media.
+    pub fn transmittance(&self, ray: &Ray<f64>, distance: f64, rng: &mut SampleRng) -> Color {
+        let grid = match &self.density {
+            Some(grid) => grid,
+            None => return self.homogeneous_transmittance(distance),
+        };
+
+        let extinction = self.extinction();
+        let majorant = extinction.max_component() * grid.max_density;
+        let (start, end) = match grid.clip(ray) {
+            Some((start, end)) if majorant > 0.0 => (start, end.min(distance)),
+            _ => return Color([1.0; 3]),
+        };
+
+        let mut transmittance = Color([1.0; 3]);
+        let mut t = start;
+        loop {
+            t += -(1.0 - rng.gen::<f64>()).ln() / majorant;
+            if t >= end {
+                return transmittance;
+            }
+
+            let density = grid.density_at(&ray.point_at(t));
+            transmittance = transmittance * (Color([1.0; 3]) - extinction * (density / majorant));
+        }
+    }
+
+    fn homogeneous_transmittance(&self, distance: f64) -> Color {
+        let [r, g, b] = self.extinction().0;
+        Color([
+            (-r * distance).exp(),
+            (-g * distance).exp(),
+            (-b * distance).exp(),
+        ])
+    }
+
+    /// Samples the distance along the ray at which it scatters inside the medium, `None` if it
+    /// reaches `max_distance` first, together with the weight for the throughput of the path.
+    pub fn sample_distance(
+        &self,
+        ray: &Ray<f64>,
+        max_distance: f64,
+        rng: &mut SampleRng,
+    ) -> (Option<f64>, Color) {
+        match &self.density {
+            Some(grid) => self.track_distance(grid, ray, max_distance, rng),
+            None => self.sample_homogeneous_distance(max_distance, rng),
+        }
+    }
+
+    /// Spectral tracking through a heterogeneous medium. Tentative collisions are sampled
+    /// against the highest extinction of the grid and become scattering, absorption or null
+    /// collisions, absorbed paths get a weight of zero.
+    fn track_distance(
+        &self,
+        grid: &VoxelGrid,
+        ray: &Ray<f64>,
+        max_distance: f64,
+        rng: &mut SampleRng,
+    ) -> (Option<f64>, Color) {
+        let extinction = self.extinction();
+        let majorant = extinction.max_component() * grid.max_density;
+        let (start, end) = match grid.clip(ray) {
+            Some((start, end)) if majorant > 0.0 => (start, end.min(max_distance)),
+            _ => return (None, Color([1.0; 3])),
+        };
+
+        let mut weight = Color([1.0; 3]);
+        let mut t = start;
+        loop {
+            t += -(1.0 - rng.gen::<f64>()).ln() / majorant;
+            if t >= end {
+                return (None, weight);
+            }
+
+            let density = grid.density_at(&ray.point_at(t));
+            let scattering = self.scattering * density;
+            let null = Color([majorant; 3]) - extinction * density;
+            let scatter_probability = average(scattering) / majorant;
+            let null_probability = average(null) / majorant;
+
+            let choice = rng.gen::<f64>();
+            if choice < scatter_probability {
+                return (
+                    Some(t),
+                    weight * scattering / (majorant * scatter_probability),
+                );
+            } else if choice < scatter_probability + null_probability {
+                weight = weight * null / (majorant * null_probability);
+            } else {
+                return (None, Color([0.0; 3]));
+            }
+        }
+    }
+
+    fn sample_homogeneous_distance(
+        &self,
+        max_distance: f64,
+        rng: &mut SampleRng,
+    ) -> (Option<f64>, Color) {
+        let extinction = self.extinction();
+        // The distance is sampled for one random color channel, the density averages all three
+        let sigma = extinction.0[rng.gen_range(0, 3)];
+        let distance = if sigma > 0.0 {
+            -(1.0 - rng.gen::<f64>()).ln() / sigma
+        } else {
+            f64::INFINITY
+        };
+
+        if distance < max_distance {
+            let transmittance = self.homogeneous_transmittance(distance);
+            let pdf = average(extinction * transmittance);
+            (Some(distance), transmittance * self.scattering / pdf)
+        } else {
+            let transmittance = self.homogeneous_transmittance(max_distance);
+            (None, transmittance / average(transmittance))
+        }
+    }
+
+    /// Henyey-Greenstein phase function for light arriving from `incoming` and leaving towards
+    /// `outgoing`, both pointing away from the scattering point.
+    pub fn phase(&self, outgoing: &Vector3<f64>, incoming: &Vector3<f64>) -> f64 {
+        let g = self.asymmetry;
+        let cos = -outgoing.dot(incoming);
+        let denominator = 1.0 + g * g - 2.0 * g * cos;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    /// Samples `incoming` proportional to the phase function, so its density equals `phase`.
+    pub fn sample_phase(&self, outgoing: &Vector3<f64>, rng: &mut SampleRng) -> Vector3<f64> {
+        let g = self.asymmetry;
+        let u = rng.gen::<f64>();
+        let cos = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let term = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - term * term) / (2.0 * g)
+        };
+        let sin = (1.0 - cos * cos).max(0.0).sqrt();
+        let angle = 2.0 * PI * rng.gen::<f64>();
+        let (tangent, bitangent) = sampling::orthonormal_basis(outgoing);
+
+        // Light continues along the direction opposite to `incoming`
+        -(tangent * (sin * angle.cos()) + bitangent * (sin * angle.sin()) + outgoing * cos)
+    }
+}
+
+fn average(color: Color) -> f64 {
+    let [r, g, b] = color.0;
+    (r + g + b) / 3.0
+}
@@ -0,0 +1,141 @@
+use nalgebra::{Isometry3, Point2, Point3, Vector3};
+use ncollide3d::query::RayIntersection;
+use ncollide3d::shape::{FeatureId, TriMesh};
+use std::path::Path;
+
+pub struct Mesh {
+    pub shape: TriMesh<f64>,
+    /// Per vertex normals used for smooth shading
+    pub normals: Vec<Vector3<f64>>,
+}
+
+/// Loads every group of a Wavefront OBJ file as its own triangulated mesh.
+/// Missing vertex normals are computed by averaging the normals of adjacent faces.
+pub fn load_obj(path: impl AsRef<Path>) -> Result<Vec<Mesh>, tobj::LoadError> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ignore_points: true,
+        ignore_lines: true,
+        ..Default::default()
+    };
+    let (models, _) = tobj::load_obj(path.as_ref(), &options)?;
+
+    Ok(models
+        .into_iter()
+        .filter(|model| !model.mesh.indices.is_empty())
+        .map(|model| {
+            let mesh = model.mesh;
+
+            let points = mesh
+                .positions
+                .chunks(3)
+                .map(|p| Point3::new(p[0] as f64, p[1] as f64, p[2] as f64))
+                .collect::<Vec<_>>();
+
+            let indices = mesh
+                .indices
+                .chunks(3)
+                .map(|i| Point3::new(i[0] as usize, i[1] as usize, i[2] as usize))
+                .collect::<Vec<_>>();
+
+            let uvs = (!mesh.texcoords.is_empty()).then(|| {
+                mesh.texcoords
+                    .chunks(2)
+                    .map(|uv| Point2::new(uv[0] as f64, uv[1] as f64))
+                    .collect::<Vec<_>>()
+            });
+
+            let normals = if mesh.normals.is_empty() {
+                face_averaged_normals(&points, &indices)
+            } else {
+                mesh.normals
+                    .chunks(3)
+                    .map(|n| Vector3::new(n[0] as f64, n[1] as f64, n[2] as f64).normalize())
+                    .collect()
+            };
+
+            Mesh {
+                shape: TriMesh::new(points, indices, uvs),
+                normals,
+            }
+        })
+        .collect())
+}
+
+fn face_averaged_normals(points: &[Point3<f64>], indices: &[Point3<usize>]) -> Vec<Vector3<f64>> {
+    let mut normals = vec![Vector3::zeros(); points.len()];
+
+    for face in indices {
+        let [a, b, c] = [points[face.x], points[face.y], points[face.z]];
+        // Not normalized so larger faces have more influence
+        let normal = (b - a).cross(&(c - a));
+        for &index in face.iter() {
+            normals[index] += normal;
+        }
+    }
+
+    normals
+        .into_iter()
+        .map(|normal| normal.try_normalize(f64::EPSILON).unwrap_or(normal))
+        .collect()
+}
+
+/// Replaces the flat face normal of a mesh intersection with the vertex normals interpolated
+/// at the hit point and interpolates the texture coordinates of the mesh if it has any.
+pub fn interpolate(
+    shape: &TriMesh<f64>,
+    normals: Option<&[Vector3<f64>]>,
+    isometry: &Isometry3<f64>,
+    local_point: &Point3<f64>,
+    intersection: &mut RayIntersection<f64>,
+) {
+    let face_index = match intersection.feature {
+        FeatureId::Face(index) => index % shape.faces().len(),
+        _ => return,
+    };
+
+    let indices = shape.faces()[face_index].indices;
+    let points = shape.points();
+    let [u, v, w] = barycentric(
+        local_point,
+        &points[indices.x],
+        &points[indices.y],
+        &points[indices.z],
+    );
+
+    if let Some(normals) = normals {
+        let local_normal = normals[indices.x] * u + normals[indices.y] * v + normals[indices.z] * w;
+        if let Some(normal) = (isometry * local_normal).try_normalize(f64::EPSILON) {
+            // Keep the side of the geometric normal for hits on the back face
+            intersection.normal = if normal.dot(&intersection.normal) < 0.0 {
+                -normal
+            } else {
+                normal
+            };
+        }
+    }
+
+    intersection.uvs = Some(shape.uvs().map_or_else(Point2::origin, |uvs| {
+        Point2::from(
+            uvs[indices.x].coords * u + uvs[indices.y].coords * v + uvs[indices.z].coords * w,
+        )
+    }));
+}
+
+fn barycentric(point: &Point3<f64>, a: &Point3<f64>, b: &Point3<f64>, c: &Point3<f64>) -> [f64; 3] {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = point - a;
+
+    let d00 = ab.dot(&ab);
+    let d01 = ab.dot(&ac);
+    let d11 = ac.dot(&ac);
+    let d20 = ap.dot(&ab);
+    let d21 = ap.dot(&ac);
+    let denominator = d00 * d11 - d01 * d01;
+
+    let v = (d11 * d20 - d01 * d21) / denominator;
+    let w = (d00 * d21 - d01 * d20) / denominator;
+    [1.0 - v - w, v, w]
+}
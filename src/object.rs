@@ -1,21 +1,42 @@
 use crate::color::Color;
-use crate::material::{Material, SurfaceType};
-use nalgebra::{Isometry3, Translation3, Unit, UnitQuaternion, Vector3};
+use crate::material::{Coloration, Material, SurfaceType};
+use crate::medium::Medium;
+use crate::mesh;
+use crate::sampling::{self, SampleRng};
+use nalgebra::{Isometry3, Point2, Point3, Translation3, Unit, UnitQuaternion, Vector3};
+use ncollide3d::bounding_volume::AABB;
 use ncollide3d::query::{Ray, RayCast, RayIntersection};
-use ncollide3d::shape::Shape;
+use ncollide3d::shape::{Ball, Cuboid, FeatureId, Plane, Shape, TriMesh};
+use rand::Rng;
+use std::f64::consts::PI;
+
+pub struct SurfaceSample {
+    pub point: Point3<f64>,
+    pub normal: Vector3<f64>,
+    /// Probability density with respect to surface area for `sample_surface` and with respect
+    /// to solid angle for `sample_towards`
+    pub pdf: f64,
+}
 
 pub struct Object {
     pub isometry: Isometry3<f64>,
     pub shape: Box<dyn Shape<f64>>,
     pub material: Material,
+    /// Per vertex normals of a `TriMesh` shape for smooth shading
+    pub vertex_normals: Option<Vec<Vector3<f64>>>,
+    /// Cumulative areas of the faces of a `TriMesh` shape starting with zero, the last entry is
+    /// the total area
+    face_area_cdf: Option<Vec<f64>>,
 }
 
 impl Object {
     pub fn new(isometry: Isometry3<f64>, shape: impl Shape<f64>, material: Material) -> Object {
         Object {
             isometry,
+            face_area_cdf: face_area_cdf(&shape),
             shape: Box::new(shape),
             material,
+            vertex_normals: None,
         }
     }
 
@@ -23,6 +44,292 @@ impl Object {
         self.shape
             .toi_and_normal_with_ray(&self.isometry, ray, 100.0, false)
     }
+
+    /// Completes the intersection of the closest hit with the shading normal and texture
+    /// coordinates.
+    pub fn interpolate(&self, ray: &Ray<f64>, intersection: &mut RayIntersection<f64>) {
+        let local_point = self
+            .isometry
+            .inverse_transform_point(&ray.point_at(intersection.toi));
+
+        if let Some(trimesh) = self.shape.as_shape::<TriMesh<f64>>() {
+            mesh::interpolate(
+                trimesh,
+                self.vertex_normals.as_deref(),
+                &self.isometry,
+                &local_point,
+                intersection,
+            );
+        } else {
+            intersection.uvs = Some(self.texture_coords(&local_point));
+        }
+    }
+
+    /// Whether the ray hits the surface from the outside. Intersection normals always face the
+    /// ray so they cannot tell the sides apart.
+    pub fn is_entering(&self, ray: &Ray<f64>, intersection: &RayIntersection<f64>) -> bool {
+        if let Some(trimesh) = self.shape.as_shape::<TriMesh<f64>>() {
+            // Hits on the back of a triangle are reported past the last face index
+            return match intersection.feature {
+                FeatureId::Face(index) => index < trimesh.faces().len(),
+                _ => true,
+            };
+        }
+
+        // Halfway to the hit the ray is still on the side it started from
+        self.shape.as_point_query().map_or(true, |query| {
+            !query.contains_point(&self.isometry, &ray.point_at(intersection.toi / 2.0))
+        })
+    }
+
+    /// Fraction of light left after the ray traveled through the inside of the object to the
+    /// intersection following the Beer-Lambert law, only refractive objects absorb light.
+    pub fn transmittance(&self, ray: &Ray<f64>, intersection: &RayIntersection<f64>) -> Color {
+        match self.material.surface {
+            SurfaceType::Refractive { absorption, .. }
+                if absorption != [0.0; 3] && !self.is_entering(ray, intersection) =>
+            {
+                let [r, g, b] = absorption;
+                let distance = intersection.toi;
+                Color([
+                    (-r * distance).exp(),
+                    (-g * distance).exp(),
+                    (-b * distance).exp(),
+                ])
+            }
+            _ => Color([1.0; 3]),
+        }
+    }
+
+    /// Uniformly samples a point on the surface, `None` for unbounded shapes.
+    pub fn sample_surface(&self, rng: &mut SampleRng) -> Option<SurfaceSample> {
+        let (point, normal, area) = if let Some(ball) = self.shape.as_shape::<Ball<f64>>() {
+            let normal = sampling::uniform_sphere(rng);
+            let radius = ball.radius();
+            (
+                Point3::from(normal * radius),
+                normal,
+                4.0 * PI * radius * radius,
+            )
+        } else if let Some(cuboid) = self.shape.as_shape::<Cuboid<f64>>() {
+            let half_extents = cuboid.half_extents();
+            let face_areas = [
+                half_extents.y * half_extents.z,
+                half_extents.x * half_extents.z,
+                half_extents.x * half_extents.y,
+            ];
+            let total_area: f64 = face_areas.iter().sum();
+
+            // Pick one of the three face pairs by area and then one of its two sides
+            let mut threshold = rng.gen::<f64>() * total_area;
+            let axis = (0..2)
+                .find(|&axis| {
+                    threshold -= face_areas[axis];
+                    threshold < 0.0
+                })
+                .unwrap_or(2);
+            let side = if rng.gen::<bool>() { 1.0 } else { -1.0 };
+
+            let mut point = Vector3::new(
+                (2.0 * rng.gen::<f64>() - 1.0) * half_extents.x,
+                (2.0 * rng.gen::<f64>() - 1.0) * half_extents.y,
+                (2.0 * rng.gen::<f64>() - 1.0) * half_extents.z,
+            );
+            point[axis] = side * half_extents[axis];
+            let mut normal = Vector3::zeros();
+            normal[axis] = side;
+
+            (Point3::from(point), normal, 8.0 * total_area)
+        } else if let Some(trimesh) = self.shape.as_shape::<TriMesh<f64>>() {
+            let points = trimesh.points();
+            let cdf = self.face_area_cdf.as_ref()?;
+            let total_area = cdf[cdf.len() - 1];
+
+            // The first face whose upper bound exceeds the threshold, skipping faces without area
+            let threshold = rng.gen::<f64>() * total_area;
+            let index =
+                match cdf[1..].binary_search_by(|bound| bound.partial_cmp(&threshold).unwrap()) {
+                    Ok(index) => index + 1,
+                    Err(index) => index,
+                }
+                .min(cdf.len() - 2);
+
+            let indices = trimesh.faces()[index].indices;
+            let [a, b, c] = [points[indices.x], points[indices.y], points[indices.z]];
+            let normal = (b - a).cross(&(c - a)).normalize();
+            (
+                sampling::uniform_triangle(&a, &b, &c, rng),
+                normal,
+                total_area,
+            )
+        } else {
+            return None;
+        };
+
+        Some(SurfaceSample {
+            point: self.isometry * point,
+            normal: self.isometry * normal,
+            pdf: 1.0 / area,
+        })
+    }
+
+    /// Samples a point on the surface visible from `origin`. Balls are sampled within the cone
+    /// they cover as seen from `origin`, all other shapes by area.
+    pub fn sample_towards(
+        &self,
+        origin: &Point3<f64>,
+        rng: &mut SampleRng,
+    ) -> Option<SurfaceSample> {
+        if let Some((center, radius, cos_max)) = self.visible_cone(origin) {
+            let direction = sampling::uniform_cone(&(center - origin).normalize(), cos_max, rng);
+
+            // Closest intersection of the sampled direction with the ball
+            let to_origin = origin - center;
+            let b = to_origin.dot(&direction);
+            let discriminant = b * b - (to_origin.norm_squared() - radius * radius);
+            let point = origin + direction * (-b - discriminant.max(0.0).sqrt());
+
+            return Some(SurfaceSample {
+                point,
+                normal: (point - center) / radius,
+                pdf: 1.0 / (2.0 * PI * (1.0 - cos_max)),
+            });
+        }
+
+        let sample = self.sample_surface(rng)?;
+        let pdf = area_to_solid_angle(sample.pdf, origin, &sample.point, &sample.normal)?;
+        Some(SurfaceSample { pdf, ..sample })
+    }
+
+    /// Probability density with respect to solid angle of `sample_towards` returning `point`.
+    pub fn pdf_towards(
+        &self,
+        origin: &Point3<f64>,
+        point: &Point3<f64>,
+        normal: &Vector3<f64>,
+    ) -> f64 {
+        if let Some((_, _, cos_max)) = self.visible_cone(origin) {
+            return 1.0 / (2.0 * PI * (1.0 - cos_max));
+        }
+
+        self.area()
+            .and_then(|area| area_to_solid_angle(1.0 / area, origin, point, normal))
+            .unwrap_or(0.0)
+    }
+
+    /// Center, radius and cosine of the half angle of the cone a ball covers as seen from
+    /// `origin`, `None` for other shapes or if `origin` is inside the ball.
+    fn visible_cone(&self, origin: &Point3<f64>) -> Option<(Point3<f64>, f64, f64)> {
+        let radius = self.shape.as_shape::<Ball<f64>>()?.radius();
+        let center = Point3::from(self.isometry.translation.vector);
+        let distance_squared = (center - origin).norm_squared();
+        if distance_squared <= radius * radius {
+            return None;
+        }
+
+        let cos_max = (1.0 - radius * radius / distance_squared).max(0.0).sqrt();
+        Some((center, radius, cos_max))
+    }
+
+    /// Whether `sample_towards` can sample the surface, emission of other shapes can only be
+    /// found by rays hitting them.
+    pub fn is_sampleable(&self) -> bool {
+        self.area().is_some()
+    }
+
+    fn area(&self) -> Option<f64> {
+        if let Some(ball) = self.shape.as_shape::<Ball<f64>>() {
+            Some(4.0 * PI * ball.radius() * ball.radius())
+        } else if let Some(cuboid) = self.shape.as_shape::<Cuboid<f64>>() {
+            let half_extents = cuboid.half_extents();
+            Some(
+                8.0 * (half_extents.y * half_extents.z
+                    + half_extents.x * half_extents.z
+                    + half_extents.x * half_extents.y),
+            )
+        } else {
+            self.face_area_cdf.as_ref().map(|cdf| cdf[cdf.len() - 1])
+        }
+    }
+
+    fn texture_coords(&self, local_point: &Point3<f64>) -> Point2<f64> {
+        if self.shape.is_shape::<Ball<f64>>() {
+            let direction = local_point.coords.normalize();
+            Point2::new(
+                0.5 + direction.z.atan2(direction.x) / (2.0 * PI),
+                0.5 + direction.y.asin() / PI,
+            )
+        } else if let Some(cuboid) = self.shape.as_shape::<Cuboid<f64>>() {
+            // Every face is mapped to the whole texture
+            let relative = local_point.coords.component_div(cuboid.half_extents());
+            let axis = relative.iamax();
+            let (u, v) = match axis {
+                0 => (relative.z * -relative.x.signum(), relative.y),
+                1 => (relative.x, relative.z * -relative.y.signum()),
+                _ => (relative.x * relative.z.signum(), relative.y),
+            };
+            Point2::new((u + 1.0) / 2.0, (v + 1.0) / 2.0)
+        } else if let Some(plane) = self.shape.as_shape::<Plane<f64>>() {
+            // One texture repetition per unit along two axes spanning the plane
+            let normal = plane.normal();
+            let helper = if normal.x.abs() < 0.9 {
+                Vector3::x()
+            } else {
+                Vector3::y()
+            };
+            let tangent = normal.cross(&helper).normalize();
+            let bitangent = normal.cross(&tangent);
+            Point2::new(
+                local_point.coords.dot(&tangent),
+                local_point.coords.dot(&bitangent),
+            )
+        } else {
+            Point2::origin()
+        }
+    }
+
+    /// World space bounding box, `None` for unbounded shapes.
+    pub fn aabb(&self) -> Option<AABB<f64>> {
+        if self.shape.is_shape::<Plane<f64>>() {
+            None
+        } else {
+            Some(self.shape.aabb(&self.isometry))
+        }
+    }
+}
+
+/// Cumulative face areas of a `TriMesh`, `None` for other shapes and empty meshes.
+fn face_area_cdf(shape: &dyn Shape<f64>) -> Option<Vec<f64>> {
+    let trimesh = shape.as_shape::<TriMesh<f64>>()?;
+    if trimesh.faces().is_empty() {
+        return None;
+    }
+
+    let points = trimesh.points();
+    let mut cdf = vec![0.0];
+    for face in trimesh.faces() {
+        cdf.push(cdf[cdf.len() - 1] + triangle_area(points, &face.indices));
+    }
+    Some(cdf)
+}
+
+fn triangle_area(points: &[Point3<f64>], indices: &Point3<usize>) -> f64 {
+    let [a, b, c] = [points[indices.x], points[indices.y], points[indices.z]];
+    (b - a).cross(&(c - a)).norm() / 2.0
+}
+
+/// Converts a density with respect to the area around `point` to one with respect to the solid
+/// angle seen from `origin`. Emitting surfaces shine from both sides.
+fn area_to_solid_angle(
+    pdf: f64,
+    origin: &Point3<f64>,
+    point: &Point3<f64>,
+    normal: &Vector3<f64>,
+) -> Option<f64> {
+    let to_point = point - origin;
+    let distance_squared = to_point.norm_squared();
+    let cos_light = normal.dot(&to_point).abs() / distance_squared.sqrt();
+    (cos_light > 0.0).then(|| pdf * distance_squared / cos_light)
 }
 
 pub struct ObjectBuilder<S: Shape<f64>> {
@@ -30,8 +337,11 @@ pub struct ObjectBuilder<S: Shape<f64>> {
     rotation: UnitQuaternion<f64>,
     shape: S,
     albedo: f64,
-    color: Color,
+    color: Coloration,
     surface: SurfaceType,
+    emission: Color,
+    medium: Option<Medium>,
+    vertex_normals: Option<Vec<Vector3<f64>>>,
 }
 
 impl<S: Shape<f64>> ObjectBuilder<S> {
@@ -43,6 +353,9 @@ impl<S: Shape<f64>> ObjectBuilder<S> {
             albedo: 0.18,
             color: [1.0; 3].into(),
             surface: SurfaceType::Diffuse,
+            emission: [0.0; 3].into(),
+            medium: None,
+            vertex_normals: None,
         }
     }
 
@@ -62,7 +375,7 @@ impl<S: Shape<f64>> ObjectBuilder<S> {
         self
     }
 
-    pub fn color(mut self, value: impl Into<Color>) -> Self {
+    pub fn color(mut self, value: impl Into<Coloration>) -> Self {
         self.color = value.into();
         self
     }
@@ -72,15 +385,35 @@ impl<S: Shape<f64>> ObjectBuilder<S> {
         self
     }
 
+    pub fn emission(mut self, value: impl Into<Color>) -> Self {
+        self.emission = value.into();
+        self
+    }
+
+    pub fn medium(mut self, value: Medium) -> Self {
+        self.medium = Some(value);
+        self
+    }
+
+    pub fn vertex_normals(mut self, normals: Vec<Vector3<f64>>) -> Self {
+        self.vertex_normals = Some(normals);
+        self
+    }
+
     pub fn build(self) -> Object {
+        let isometry = Isometry3::from_parts(self.translation, self.rotation);
         Object {
-            isometry: Isometry3::from_parts(self.translation, self.rotation),
+            isometry,
+            face_area_cdf: face_area_cdf(&self.shape),
             shape: Box::new(self.shape),
             material: Material {
                 color: self.color,
                 albedo: self.albedo,
                 surface: self.surface,
+                emission: self.emission,
+                medium: self.medium.map(|medium| medium.placed(isometry)),
             },
+            vertex_normals: self.vertex_normals,
         }
     }
 }
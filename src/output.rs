@@ -0,0 +1,192 @@
+use crate::color::Color;
+use crate::tone_mapping::ToneMapping;
+use image::codecs::hdr::HdrEncoder;
+use image::{ImageBuffer, ImageResult, Rgb, Rgba, RgbaImage};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Linear radiance of every pixel without any clamping.
+pub type Framebuffer = ImageBuffer<Rgb<f32>, Vec<f32>>;
+
+/// Saves the framebuffer in the format given by the file extension. OpenEXR `.exr` and
+/// Radiance `.hdr` files keep the full range, any other format is tone mapped to 8 bit.
+pub fn save(
+    framebuffer: &Framebuffer,
+    tone_mapping: &ToneMapping,
+    path: impl AsRef<Path>,
+) -> ImageResult<()> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("exr") => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            write_exr(framebuffer, &mut writer)?;
+            writer.flush()?;
+            Ok(())
+        }
+        Some("hdr") => {
+            let pixels = framebuffer.pixels().cloned().collect::<Vec<_>>();
+            HdrEncoder::new(BufWriter::new(File::create(path)?)).encode(
+                &pixels,
+                framebuffer.width() as usize,
+                framebuffer.height() as usize,
+            )
+        }
+        _ => to_rgba(framebuffer, tone_mapping).save(path),
+    }
+}
+
+/// Converts the framebuffer to an 8 bit image for display.
+pub fn to_rgba(framebuffer: &Framebuffer, tone_mapping: &ToneMapping) -> RgbaImage {
+    RgbaImage::from_fn(framebuffer.width(), framebuffer.height(), |x, y| {
+        let [r, g, b] = framebuffer.get_pixel(x, y).0;
+        Rgba(
+            tone_mapping
+                .apply(Color([r as f64, g as f64, b as f64]))
+                .to_u8(),
+        )
+    })
+}
+
+/// Writes an uncompressed scanline OpenEXR image with 32 bit float channels.
+fn write_exr(framebuffer: &Framebuffer, writer: &mut impl Write) -> io::Result<()> {
+    const FLOAT: i32 = 2;
+    // Channels are stored in alphabetical order
+    const CHANNELS: [(&str, usize); 3] = [("B", 2), ("G", 1), ("R", 0)];
+
+    let width = framebuffer.width() as i32;
+    let height = framebuffer.height() as i32;
+
+    let mut channels = Vec::new();
+    for (name, _) in &CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&FLOAT.to_le_bytes());
+        // Not perceptually linear followed by three reserved bytes
+        channels.extend_from_slice(&[0; 4]);
+        // Sampling in x and y
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+
+    let mut window = Vec::new();
+    for value in &[0, 0, width - 1, height - 1] {
+        window.extend_from_slice(&i32::to_le_bytes(*value));
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2i32.to_le_bytes());
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+    attribute("channels", "chlist", &channels);
+    attribute("compression", "compression", &[0]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+
+    // Every uncompressed block holds one scanline after its row and size
+    let line_size = width as usize * CHANNELS.len() * 4;
+    let block_size = 8 + line_size;
+    let first_block = header.len() + height as usize * 8;
+
+    writer.write_all(&header)?;
+    for y in 0..height as usize {
+        writer.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+    }
+
+    for (y, row) in framebuffer.rows().enumerate() {
+        let row = row.collect::<Vec<_>>();
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(line_size as i32).to_le_bytes())?;
+        for (_, channel) in &CHANNELS {
+            for pixel in &row {
+                writer.write_all(&pixel.0[*channel].to_le_bytes())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_i32(bytes: &[u8], offset: usize) -> i32 {
+        let mut value = [0; 4];
+        value.copy_from_slice(&bytes[offset..offset + 4]);
+        i32::from_le_bytes(value)
+    }
+
+    fn read_f32(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_bits(read_i32(bytes, offset) as u32)
+    }
+
+    fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+        let mut value = [0; 8];
+        value.copy_from_slice(&bytes[offset..offset + 8]);
+        u64::from_le_bytes(value)
+    }
+
+    /// Skips a null terminated string and returns the offset after it.
+    fn skip_string(bytes: &[u8], offset: usize) -> usize {
+        offset + bytes[offset..].iter().position(|&byte| byte == 0).unwrap() + 1
+    }
+
+    #[test]
+    fn exr_layout() {
+        let (width, height) = (3, 2);
+        let framebuffer = Framebuffer::from_fn(width, height, |x, y| {
+            let value = (x + 10 * y) as f32;
+            Rgb([value, value + 0.25, value + 0.5])
+        });
+        let mut bytes = Vec::new();
+        write_exr(&framebuffer, &mut bytes).unwrap();
+
+        assert_eq!(bytes[..4], [0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(read_i32(&bytes, 4), 2);
+
+        // Attributes are name, type, size and value until an empty name
+        let mut offset = 8;
+        while bytes[offset] != 0 {
+            offset = skip_string(&bytes, offset);
+            offset = skip_string(&bytes, offset);
+            offset += 4 + read_i32(&bytes, offset) as usize;
+        }
+        let offset_table = offset + 1;
+
+        let line_size = width as usize * 3 * 4;
+        let first_block = offset_table + height as usize * 8;
+        for y in 0..height as usize {
+            let block = read_u64(&bytes, offset_table + y * 8) as usize;
+            assert_eq!(block, first_block + y * (8 + line_size));
+            assert_eq!(read_i32(&bytes, block), y as i32);
+            assert_eq!(read_i32(&bytes, block + 4), line_size as i32);
+        }
+        assert_eq!(bytes.len(), first_block + height as usize * (8 + line_size));
+
+        // Pixel (1, 1) of the second scanline, channels in B, G, R order
+        let block = read_u64(&bytes, offset_table + 8) as usize;
+        let pixel = |channel: usize| read_f32(&bytes, block + 8 + channel * width as usize * 4 + 4);
+        assert_eq!(pixel(0), 11.5);
+        assert_eq!(pixel(1), 11.25);
+        assert_eq!(pixel(2), 11.0);
+    }
+}
@@ -0,0 +1,30 @@
+use glutin_window::GlutinWindow as Window;
+use image::RgbaImage;
+use opengl_graphics::{GlGraphics, OpenGL, Texture, TextureSettings};
+use piston::event_loop::{EventLoop, EventSettings, Events};
+use piston::input::RenderEvent;
+use piston::window::WindowSettings;
+
+pub fn show(image: &RgbaImage) {
+    let opengl = OpenGL::V4_5;
+
+    let mut window: Window = WindowSettings::new("Ray Tracer", [image.width(), image.height()])
+        .graphics_api(opengl)
+        .exit_on_esc(true)
+        .build()
+        .unwrap();
+
+    let mut events = Events::new(EventSettings::new().lazy(true));
+    let mut gl = GlGraphics::new(opengl);
+
+    let texture_settings = TextureSettings::new();
+    let texture = Texture::from_image(image, &texture_settings);
+
+    while let Some(event) = events.next(&mut window) {
+        if let Some(render_args) = event.render_args() {
+            gl.draw(render_args.viewport(), |c, gl| {
+                graphics::image(&texture, c.transform, gl);
+            });
+        }
+    }
+}
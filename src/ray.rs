@@ -1,22 +1,40 @@
-use crate::{PIXEL_HEIGHT, PIXEL_WIDTH};
-use nalgebra::{Perspective3, Point3, Vector3};
+use crate::camera::Camera;
+use crate::sampling::{self, SampleRng};
+use nalgebra::{Point3, Vector3};
 use ncollide3d::query::Ray;
+use rand::Rng;
 
-pub fn create_prime(x: u32, y: u32, perspective: &Perspective3<f64>) -> Ray<f64> {
+pub fn create_prime(
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    camera: &Camera,
+    rng: &mut SampleRng,
+) -> Ray<f64> {
     const SIZE: f64 = 2.0;
-    const NORMALIZED_WIDTH: f64 = SIZE / PIXEL_WIDTH as f64;
-    const NORMALIZED_HEIGHT: f64 = SIZE / PIXEL_HEIGHT as f64;
+    let normalized_width = SIZE / width as f64;
+    let normalized_height = SIZE / height as f64;
 
-    let normalized_x = NORMALIZED_WIDTH * (x as f64 + rand::random::<f64>()) - 1.0;
-    let normalized_y = 1.0 - NORMALIZED_HEIGHT * (y as f64 + rand::random::<f64>());
+    let normalized_x = normalized_width * (x as f64 + rng.gen::<f64>()) - 1.0;
+    let normalized_y = 1.0 - normalized_height * (y as f64 + rng.gen::<f64>());
 
-    let near_point = Point3::new(normalized_x, normalized_y, -1.0);
-    let far_point = Point3::new(normalized_x, normalized_y, 1.0);
+    let aspect_ratio = width as f64 / height as f64;
+    let scale = (camera.fov.to_radians() / 2.0).tan();
+    let direction = Vector3::new(
+        normalized_x * aspect_ratio * scale,
+        normalized_y * scale,
+        -1.0,
+    );
 
-    let origin = perspective.unproject_point(&near_point);
-    let direction = (perspective.unproject_point(&far_point) - &origin).normalize();
+    // Rays through the lens converge on the focal plane at z = -focus_distance
+    let focus_point = direction * camera.focus_distance;
+    let lens_point = sampling::uniform_disk(rng) * camera.aperture;
 
-    Ray::new(origin, direction)
+    Ray::new(
+        camera.position + camera.rotation * lens_point,
+        (camera.rotation * (focus_point - lens_point)).normalize(),
+    )
 }
 
 pub fn create_reflection(
@@ -0,0 +1,91 @@
+use nalgebra::{Point3, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64Mcg;
+use std::f64::consts::PI;
+
+/// Random number generator used for all sampling decisions while rendering.
+pub type SampleRng = Pcg64Mcg;
+
+/// Creates the random number stream of a pixel. The stream only depends on the seed and the
+/// pixel coordinates so renders are reproducible regardless of how pixels are scheduled.
+pub fn pixel_rng(seed: u64, x: u32, y: u32) -> SampleRng {
+    SampleRng::seed_from_u64(mix(mix(seed ^ x as u64) ^ y as u64))
+}
+
+/// SplitMix64 finalizer, spreads nearby inputs over the whole value range.
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub fn uniform_disk(rng: &mut SampleRng) -> Vector3<f64> {
+    let radius = rng.gen::<f64>().sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    Vector3::new(radius * angle.cos(), radius * angle.sin(), 0.0)
+}
+
+/// Direction in the hemisphere around `normal` with a density proportional to the cosine
+/// to the normal.
+pub fn cosine_hemisphere(normal: &Vector3<f64>, rng: &mut SampleRng) -> Vector3<f64> {
+    let disk = uniform_disk(rng);
+    let height = (1.0 - disk.norm_squared()).max(0.0).sqrt();
+    let (tangent, bitangent) = orthonormal_basis(normal);
+
+    (tangent * disk.x + bitangent * disk.y + normal * height).normalize()
+}
+
+/// Uniformly distributed direction within the cone around `axis` whose half angle has the
+/// cosine `cos_max`.
+pub fn uniform_cone(axis: &Vector3<f64>, cos_max: f64, rng: &mut SampleRng) -> Vector3<f64> {
+    let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    let (tangent, bitangent) = orthonormal_basis(axis);
+
+    (tangent * (sin_theta * angle.cos()) + bitangent * (sin_theta * angle.sin()) + axis * cos_theta)
+        .normalize()
+}
+
+/// Two unit vectors perpendicular to `normal` and to each other.
+pub fn orthonormal_basis(normal: &Vector3<f64>) -> (Vector3<f64>, Vector3<f64>) {
+    // Any tangent works since callers only need a basis around the normal
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let tangent = normal.cross(&helper).normalize();
+    (tangent, normal.cross(&tangent))
+}
+
+/// Weight of a sample from a strategy with density `pdf` when combined with a second strategy
+/// with density `other_pdf`.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf = pdf * pdf;
+    let other_pdf = other_pdf * other_pdf;
+    if pdf + other_pdf > 0.0 {
+        pdf / (pdf + other_pdf)
+    } else {
+        0.0
+    }
+}
+
+pub fn uniform_sphere(rng: &mut SampleRng) -> Vector3<f64> {
+    let z = 1.0 - 2.0 * rng.gen::<f64>();
+    let radius = (1.0 - z * z).max(0.0).sqrt();
+    let angle = 2.0 * PI * rng.gen::<f64>();
+    Vector3::new(radius * angle.cos(), radius * angle.sin(), z)
+}
+
+pub fn uniform_triangle(
+    a: &Point3<f64>,
+    b: &Point3<f64>,
+    c: &Point3<f64>,
+    rng: &mut SampleRng,
+) -> Point3<f64> {
+    let u = rng.gen::<f64>().sqrt();
+    let v = rng.gen::<f64>();
+    a + (b - a) * (u * (1.0 - v)) + (c - a) * (u * v)
+}
@@ -1,200 +1,333 @@
+use crate::bvh::Bvh;
+use crate::camera::Camera;
 use crate::color::Color;
+use crate::environment::Environment;
+use crate::integrator::{Integrator, SHADOW_BIAS};
 use crate::light::Light;
-use crate::material::SurfaceType;
+use crate::medium::Medium;
 use crate::object::Object;
-use crate::{ray, PIXEL_HEIGHT, PIXEL_WIDTH};
-use image::{ImageBuffer, RgbaImage};
-use nalgebra::{Perspective3, Point3, Vector3};
+use crate::output::Framebuffer;
+use crate::ray;
+use crate::sampling::{self, SampleRng};
+use crate::tone_mapping::ToneMapping;
+use nalgebra::{Point3, Vector3};
 use ncollide3d::query::{Ray, RayIntersection};
 use rayon::prelude::*;
-use std::f64::consts::PI;
+use std::ptr;
 
-const SHADOW_BIAS: f64 = 1e-13;
+pub struct LightSample {
+    pub direction: Vector3<f64>,
+    /// Radiance arriving from the direction, for lights which rays cannot hit the irradiance
+    pub radiance: Color,
+    /// Probability density of the direction with respect to solid angle, `None` for lights
+    /// which rays cannot hit and can only be reached by sampling them
+    pub pdf: Option<f64>,
+}
 
 pub struct Scene {
-    pub perspective: Perspective3<f64>,
+    pub camera: Camera,
     pub objects: Vec<Object>,
+    pub bvh: Bvh,
     pub lights: Vec<Light>,
+    /// Indices of the objects with an emissive material
+    pub emitters: Vec<usize>,
+    /// Medium filling the space outside of all objects
+    pub fog: Option<Medium>,
+    /// Light arriving from rays that miss all objects, black without it
+    pub environment: Option<Environment>,
 
+    pub width: u32,
+    pub height: u32,
     pub max_recursion_depth: u32,
     pub max_rays: u32,
+    /// Seed of the random numbers, renders with the same seed are identical
+    pub seed: u64,
+    pub integrator: Box<dyn Integrator>,
+    /// Maps the radiance to 8 bit images
+    pub tone_mapping: ToneMapping,
 }
 
 impl Scene {
-    pub fn create_image(&self) -> RgbaImage {
-        let number_of_rays = self.max_rays as f64 * (1.0 + self.lights.len() as f64);
-        let pixels = (0..PIXEL_HEIGHT)
+    pub fn create_image(&self) -> Framebuffer {
+        let number_of_rays = self.max_rays as f64;
+        let pixels = (0..self.height)
             .into_par_iter()
             .flat_map(|y| {
-                (0..PIXEL_WIDTH)
+                (0..self.width)
                     .flat_map(|x| {
+                        let mut rng = sampling::pixel_rng(self.seed, x, y);
                         ((0..self.max_rays)
                             .map(|_| {
-                                let ray = ray::create_prime(x, y, &self.perspective);
-                                self.cast_ray(&ray, self.max_recursion_depth)
+                                let ray = ray::create_prime(
+                                    x,
+                                    y,
+                                    self.width,
+                                    self.height,
+                                    &self.camera,
+                                    &mut rng,
+                                );
+                                self.integrator.radiance(self, ray, &mut rng)
                             })
                             .sum::<Color>()
                             / number_of_rays)
-                            .to_u8()
-                            .to_vec()
+                            .0
+                            .iter()
+                            .map(|&value| value as f32)
+                            .collect::<Vec<_>>()
                     })
-                    .collect::<Vec<u8>>()
+                    .collect::<Vec<f32>>()
             })
-            .collect::<Vec<u8>>();
+            .collect::<Vec<f32>>();
 
-        ImageBuffer::from_vec(PIXEL_WIDTH, PIXEL_HEIGHT, pixels).unwrap()
+        Framebuffer::from_vec(self.width, self.height, pixels).unwrap()
     }
 
-    fn get_color(
+    /// Samples one point on every emissive object except `object` itself and returns the
+    /// samples which are not occluded from `origin`. Samples behind `surface_normal` are skipped,
+    /// `medium` is the medium around `origin`.
+    pub fn sample_emitters(
         &self,
-        ray: &Ray<f64>,
-        object: &Object,
-        intersection: &RayIntersection<f64>,
-        depth: u32,
-    ) -> Color {
-        let hit_point = ray.point_at(intersection.toi);
+        object: Option<&Object>,
+        origin: &Point3<f64>,
+        surface_normal: Option<&Vector3<f64>>,
+        medium: Option<&Medium>,
+        rng: &mut SampleRng,
+    ) -> Vec<LightSample> {
+        self.emitters
+            .iter()
+            .map(|&index| &self.objects[index])
+            .filter(|emitter| object.map_or(true, |object| !ptr::eq(*emitter, object)))
+            .filter_map(|emitter| {
+                let sample = emitter.sample_towards(origin, rng)?;
+                let to_light = sample.point - origin;
+                let distance = to_light.norm();
+                let direction = to_light / distance;
 
-        match object.material.surface {
-            SurfaceType::Diffuse => {
-                self.shade_diffuse(object, &hit_point, &intersection.normal, depth)
-            }
-            SurfaceType::Reflective { reflectivity, fuzz } => {
-                let reflection_ray = ray::create_reflection(
-                    intersection.normal,
-                    ray.dir + fuzz * Vector3::new_random().normalize(),
-                    hit_point,
-                    SHADOW_BIAS,
-                );
-                let mut color = self.shade_diffuse(object, &hit_point, &intersection.normal, depth);
-                color = color * (1.0 - reflectivity);
-                color + self.cast_ray(&reflection_ray, depth - 1) * reflectivity
-            }
-            SurfaceType::Refractive {
-                transparency,
-                index,
-            } => {
-                let mut refraction_color = Color([0.0; 3]);
-                let kr = Self::fresnel(ray.dir, intersection.normal, index);
-                let surface_color = object.material.color;
-                //.color_at(&intersection.object.texture_coords(&hit_point));
-
-                if kr < 1.0 {
-                    let transmission_ray = ray::create_transmission(
-                        intersection.normal,
-                        ray.dir,
-                        hit_point,
-                        SHADOW_BIAS,
-                        index,
-                    )
-                    .unwrap();
-
-                    refraction_color = self.cast_ray(&transmission_ray, depth - 1);
+                if surface_normal.map_or(false, |normal| normal.dot(&direction) <= 0.0) {
+                    return None;
                 }
 
-                let reflection_ray =
-                    ray::create_reflection(intersection.normal, ray.dir, hit_point, SHADOW_BIAS);
-                let reflection_color = self.cast_ray(&reflection_ray, depth - 1);
+                // Stop just short of the emitter's surface
+                let transmittance = self.transmittance(
+                    &Ray::new(*origin, direction),
+                    distance * (1.0 - 1e-6),
+                    medium,
+                    rng,
+                )?;
 
-                (reflection_color * kr + refraction_color * (1.0 - kr))
-                    * transparency
-                    * surface_color
-            }
-        }
+                Some(LightSample {
+                    direction,
+                    radiance: emitter.material.emission * transmittance,
+                    pdf: Some(sample.pdf),
+                })
+            })
+            .collect()
     }
 
-    fn shade_diffuse(
+    /// Samples the lights and the environment of the scene that are not occluded from `origin`.
+    /// Lights behind `surface_normal` are skipped, `medium` is the medium around `origin`.
+    pub fn sample_lights(
         &self,
-        object: &Object,
-        hit_point: &Point3<f64>,
-        surface_normal: &Vector3<f64>,
-        depth: u32,
-    ) -> Color {
-        let origin = hit_point + surface_normal * SHADOW_BIAS;
-        let light_reflected = object.material.albedo / PI;
-
-        let scatter_color = {
-            let scatter_ray = Ray::new(
-                origin,
-                ((hit_point + surface_normal + Vector3::new_random().normalize()) - origin)
-                    .normalize(),
-            );
+        origin: &Point3<f64>,
+        surface_normal: Option<&Vector3<f64>>,
+        medium: Option<&Medium>,
+        rng: &mut SampleRng,
+    ) -> Vec<LightSample> {
+        let mut samples = self
+            .lights
+            .iter()
+            .filter_map(|light| {
+                let light_point = light.sample(origin, rng)?;
+                let direction = light_point.direction;
+                if surface_normal.map_or(false, |normal| normal.dot(&direction) <= 0.0) {
+                    return None;
+                }
 
-            object.material.color
-                * self.cast_ray(&scatter_ray, depth - 1)
-                * surface_normal.dot(&scatter_ray.dir).max(0.0)
-                * light_reflected
-        };
+                let transmittance = self.transmittance(
+                    &Ray::new(*origin, direction),
+                    light_point.distance,
+                    medium,
+                    rng,
+                )?;
 
+                let radiance = light_point
+                    .pdf
+                    .map_or(light_point.irradiance, |pdf| light_point.irradiance * pdf);
+                Some(LightSample {
+                    direction,
+                    radiance: radiance * transmittance,
+                    pdf: light_point.pdf,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        samples.extend(self.sample_environment(origin, surface_normal, medium, rng));
+        samples
+    }
+
+    fn sample_environment(
+        &self,
+        origin: &Point3<f64>,
+        surface_normal: Option<&Vector3<f64>>,
+        medium: Option<&Medium>,
+        rng: &mut SampleRng,
+    ) -> Option<LightSample> {
+        let environment = self.environment.as_ref()?;
+        let (direction, pdf) = environment.sample(rng)?;
+        if surface_normal.map_or(false, |normal| normal.dot(&direction) <= 0.0) {
+            return None;
+        }
+
+        let transmittance =
+            self.transmittance(&Ray::new(*origin, direction), f64::INFINITY, medium, rng)?;
+        Some(LightSample {
+            direction,
+            radiance: environment.radiance(&direction) * transmittance,
+            pdf: Some(pdf),
+        })
+    }
+
+    /// Closest light with a surface hit by `ray` before `max_distance`, with the distance to it
+    /// and the radiance leaving it.
+    pub fn hit_light(&self, ray: &Ray<f64>, max_distance: f64) -> Option<(&Light, f64, Color)> {
         self.lights
             .iter()
-            .map(|light| {
-                let direction_to_light = light.direction_to_light(&hit_point);
-                let shadow_ray = Ray::new(origin, direction_to_light);
-                let light_color = self
-                    .trace(&shadow_ray)
-                    .map(|(object, intersection)| {
-                        if let SurfaceType::Refractive { .. } = object.material.surface {
-                            light.color() + self.cast_ray(&shadow_ray, depth - 1)
-                        } else if intersection.toi > light.distance_to(&hit_point) {
-                            light.color() * light.intensity(&hit_point) // is hitted object behind light
-                        } else {
-                            [0.1; 3].into()
-                        }
-                    })
-                    .unwrap_or([0.1; 3].into());
-
-                let light_power = surface_normal.dot(&direction_to_light).max(0.0);
+            .filter_map(|light| {
+                let (distance, radiance) = light.hit(ray)?;
+                (distance < max_distance).then(|| (light, distance, radiance))
+            })
+            .min_by(|(_, a, _), (_, b, _)| a.partial_cmp(b).unwrap())
+    }
 
-                object.material.color
-                        //.color_at(&intersection.object.texture_coords(&hit_point))
-                        * light_color
-                        * light_power
-                        * light_reflected
+    /// Light arriving from `direction` when a ray misses all objects.
+    pub fn background(&self, direction: &Vector3<f64>) -> Color {
+        self.environment
+            .as_ref()
+            .map_or(Color([0.0; 3]), |environment| {
+                environment.radiance(direction)
             })
-            .sum::<Color>()
-            + scatter_color
     }
 
-    fn fresnel(incident: Vector3<f64>, normal: Vector3<f64>, index: f64) -> f64 {
-        let i_dot_n = incident.dot(&normal).clamp(-1.0, 1.0);
-        let mut eta_i = 1.0;
-        let mut eta_t = index;
-        if i_dot_n > 0.0 {
-            eta_i = eta_t;
-            eta_t = 1.0;
+    /// Fraction of light passing along `ray` up to `distance`, `None` if an opaque object is in
+    /// the way. Surfaces bounding a medium are crossed, `medium` is the medium around the origin.
+    pub fn transmittance<'a>(
+        &'a self,
+        ray: &Ray<f64>,
+        distance: f64,
+        mut medium: Option<&'a Medium>,
+        rng: &mut SampleRng,
+    ) -> Option<Color> {
+        let mut transmittance = Color([1.0; 3]);
+        let mut ray = *ray;
+        let mut remaining = distance;
+
+        loop {
+            let hit = self
+                .bvh
+                .trace(&self.objects, &ray)
+                .filter(|(_, intersection)| intersection.toi < remaining);
+
+            let (object, intersection) = match hit {
+                Some(hit) => hit,
+                None => {
+                    return Some(medium.map_or(transmittance, |medium| {
+                        transmittance * medium.transmittance(&ray, remaining, rng)
+                    }))
+                }
+            };
+
+            if object.material.medium.is_none() {
+                return None;
+            }
+
+            if let Some(medium) = medium {
+                transmittance = transmittance * medium.transmittance(&ray, intersection.toi, rng);
+            }
+            medium = self.medium_behind(object, &ray, &intersection);
+            remaining -= intersection.toi;
+            ray = Ray::new(
+                ray.point_at(intersection.toi) - intersection.normal * SHADOW_BIAS,
+                ray.dir,
+            );
         }
+    }
 
-        let sin_t = eta_i / eta_t * (1.0 - i_dot_n * i_dot_n).max(0.0).sqrt();
-        if sin_t >= 1.0 {
-            //Total internal reflection
-            1.0
+    /// Medium a ray is in after crossing the surface of `object`.
+    pub fn medium_behind<'a>(
+        &'a self,
+        object: &'a Object,
+        ray: &Ray<f64>,
+        intersection: &RayIntersection<f64>,
+    ) -> Option<&'a Medium> {
+        if object.is_entering(ray, intersection) {
+            object.material.medium.as_ref()
         } else {
-            let cos_t = (1.0 - sin_t * sin_t).max(0.0).sqrt();
-            let cos_i = i_dot_n.abs();
-            let r_s = ((eta_t * cos_i) - (eta_i * cos_t)) / ((eta_t * cos_i) + (eta_i * cos_t));
-            let r_p = ((eta_i * cos_i) - (eta_t * cos_t)) / ((eta_i * cos_i) + (eta_t * cos_t));
-            (r_s * r_s + r_p * r_p) / 2.0
+            self.fog.as_ref()
         }
     }
 
-    fn trace(&self, ray: &Ray<f64>) -> Option<(&Object, RayIntersection<f64>)> {
-        self.objects
-            .iter()
-            .filter_map(|object| {
-                object
-                    .intersect(ray)
-                    .map(|intersection| (object, intersection))
+    pub fn trace(&self, ray: &Ray<f64>) -> Option<(&Object, RayIntersection<f64>)> {
+        self.bvh
+            .trace(&self.objects, ray)
+            .map(|(object, mut intersection)| {
+                object.interpolate(ray, &mut intersection);
+                (object, intersection)
             })
-            .min_by(|(_, a), (_, b)| a.toi.partial_cmp(&b.toi).unwrap())
     }
+}
 
-    pub fn cast_ray(&self, ray: &Ray<f64>, depth: u32) -> Color {
-        if depth == 0 {
-            return Color([0.0; 3]);
-        }
+#[cfg(test)]
+mod tests {
+    use crate::scene_file;
+    use std::path::Path;
+
+    const SCENE: &str = r#"
+        width = 8
+        height = 6
+        max_recursion_depth = 3
+        max_rays = 4
+        integrator = "path"
+
+        [camera]
+        position = [0.0, 1.0, 3.0]
+        target = [0.0, 0.5, 0.0]
+        fov = 60.0
+
+        [[lights]]
+        type = "spherical"
+        position = [1.0, 3.0, 1.0]
+        radius = 0.5
+        color = [1.0, 1.0, 1.0]
+        intensity = 50.0
+
+        [[objects]]
+        shape = { type = "plane", normal = [0.0, 1.0, 0.0] }
+
+        [[objects]]
+        shape = { type = "ball", radius = 0.5 }
+        position = [0.0, 0.5, 0.0]
+    "#;
+
+    /// Bit patterns of the rendered pixels, which unlike floats compare NaN as equal.
+    fn render(seed: u64) -> Vec<u32> {
+        let mut scene =
+            scene_file::parse(SCENE, Path::new("")).unwrap_or_else(|error| panic!("{}", error));
+        scene.seed = seed;
+        scene
+            .create_image()
+            .into_raw()
+            .into_iter()
+            .map(f32::to_bits)
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_renders_identical_images() {
+        assert_eq!(render(7), render(7));
+    }
 
-        self.trace(ray)
-            .map(|(object, intersection)| self.get_color(ray, &object, &intersection, depth))
-            .unwrap_or(Color([0.0; 3]))
+    #[test]
+    fn different_seeds_render_different_images() {
+        assert_ne!(render(7), render(8));
     }
 }
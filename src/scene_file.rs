@@ -0,0 +1,885 @@
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::environment::{Environment, EnvironmentMap};
+use crate::integrator;
+use crate::light::{DirectionalLight, DiskLight, Light, RectangleLight, SphericalLight, SpotLight};
+use crate::material::{Coloration, SurfaceType};
+use crate::medium::Medium;
+use crate::mesh;
+use crate::object::{Object, ObjectBuilder};
+use crate::scene::Scene;
+use crate::sky::Sky;
+use crate::texture::{Filter, Texture, Wrap};
+use crate::tone_mapping::{Operator, ToneMapping};
+use crate::volume::VoxelGrid;
+use nalgebra::{Point3, Unit, Vector3};
+use ncollide3d::shape::{self, Shape};
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    #[serde(default = "SceneDescription::default_width")]
+    width: NonZeroU32,
+    #[serde(default = "SceneDescription::default_height")]
+    height: NonZeroU32,
+    max_recursion_depth: u32,
+    max_rays: NonZeroU32,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "SceneDescription::default_integrator")]
+    integrator: IntegratorName,
+    #[serde(default)]
+    tone_mapping: ToneMappingDescription,
+    /// Medium filling the space between the objects
+    fog: Option<MediumDescription>,
+    environment: Option<EnvironmentDescription>,
+    camera: CameraDescription,
+    #[serde(default)]
+    lights: Vec<LightDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+}
+
+impl SceneDescription {
+    fn default_width() -> NonZeroU32 {
+        NonZeroU32::new(800).unwrap()
+    }
+
+    fn default_height() -> NonZeroU32 {
+        NonZeroU32::new(600).unwrap()
+    }
+
+    fn default_integrator() -> IntegratorName {
+        IntegratorName("whitted".to_string())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToneMappingDescription {
+    #[serde(default = "ToneMappingDescription::default_operator")]
+    operator: Operator,
+    /// In stops, every stop doubles the brightness
+    #[serde(default)]
+    exposure: f64,
+    #[serde(default = "ToneMappingDescription::default_white_point")]
+    white_point: Positive,
+}
+
+impl ToneMappingDescription {
+    fn default_operator() -> Operator {
+        ToneMapping::default().operator
+    }
+
+    fn default_white_point() -> Positive {
+        Positive(ToneMapping::default().white_point)
+    }
+}
+
+impl Default for ToneMappingDescription {
+    fn default() -> Self {
+        ToneMappingDescription {
+            operator: Self::default_operator(),
+            exposure: 0.0,
+            white_point: Self::default_white_point(),
+        }
+    }
+}
+
+/// Camera whose view direction and up vector give a valid orientation
+#[derive(Deserialize)]
+#[serde(try_from = "CameraFields")]
+struct CameraDescription(CameraFields);
+
+impl TryFrom<CameraFields> for CameraDescription {
+    type Error = &'static str;
+
+    fn try_from(value: CameraFields) -> Result<Self, Self::Error> {
+        let view = Vector3::from(value.target) - Vector3::from(value.position);
+        let view_length = view.norm();
+        if view_length <= f64::EPSILON {
+            Err("camera position and target must differ")
+        } else if (view / view_length).cross(&value.up.0).norm() < 1e-6 {
+            Err("camera up must not be parallel to the view direction")
+        } else {
+            Ok(CameraDescription(value))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraFields {
+    #[serde(default)]
+    position: [f64; 3],
+    #[serde(default = "CameraFields::default_target")]
+    target: [f64; 3],
+    #[serde(default = "CameraFields::default_up")]
+    up: Direction,
+    fov: Positive,
+    #[serde(default)]
+    aperture: f64,
+    focus_distance: Option<Positive>,
+}
+
+impl CameraFields {
+    fn default_target() -> [f64; 3] {
+        [0.0, 0.0, -1.0]
+    }
+
+    fn default_up() -> Direction {
+        Direction(Vector3::y_axis())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum EnvironmentDescription {
+    /// Equirectangular Radiance `.hdr` image relative to the scene file
+    Image {
+        file: PathBuf,
+        #[serde(default = "EnvironmentDescription::default_intensity")]
+        intensity: f64,
+    },
+    /// Daylight sky, the sun is added as a directional light
+    Sky {
+        /// Direction the sunlight travels
+        sun_direction: Direction,
+        #[serde(default = "EnvironmentDescription::default_turbidity")]
+        turbidity: Turbidity,
+        #[serde(default = "EnvironmentDescription::default_ground_albedo")]
+        ground_albedo: [f64; 3],
+        #[serde(default = "EnvironmentDescription::default_intensity")]
+        intensity: f64,
+    },
+}
+
+impl EnvironmentDescription {
+    fn default_intensity() -> f64 {
+        1.0
+    }
+
+    fn default_turbidity() -> Turbidity {
+        Turbidity(3.0)
+    }
+
+    fn default_ground_albedo() -> [f64; 3] {
+        [0.3, 0.3, 0.3]
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum LightDescription {
+    Directional {
+        direction: Direction,
+        color: [f64; 3],
+        intensity: f64,
+    },
+    Spherical {
+        position: [f64; 3],
+        /// Zero for a point light with hard shadows
+        #[serde(default = "LightDescription::default_radius")]
+        radius: NonNegative,
+        color: [f64; 3],
+        intensity: f64,
+    },
+    /// Cone angles are in degree from the direction
+    Spot {
+        position: [f64; 3],
+        direction: Direction,
+        color: [f64; 3],
+        intensity: f64,
+        inner_angle: ConeAngle,
+        outer_angle: ConeAngle,
+    },
+    /// Shines to the side of its normal, the width runs horizontally
+    Rectangle {
+        position: [f64; 3],
+        normal: Direction,
+        size: [Positive; 2],
+        color: [f64; 3],
+        intensity: f64,
+    },
+    /// Shines to the side of its normal
+    Disk {
+        position: [f64; 3],
+        normal: Direction,
+        radius: Positive,
+        color: [f64; 3],
+        intensity: f64,
+    },
+}
+
+impl LightDescription {
+    fn default_radius() -> NonNegative {
+        NonNegative(0.2)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ObjectDescription {
+    shape: ShapeDescription,
+    #[serde(default)]
+    position: [f64; 3],
+    rotation: Option<RotationDescription>,
+    color: Option<ColorDescription>,
+    albedo: Option<f64>,
+    surface: Option<SurfaceType>,
+    emission: Option<[f64; 3]>,
+    /// Medium filling the object, its surface only bounds the medium
+    medium: Option<MediumDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediumDescription {
+    #[serde(default)]
+    absorption: [f64; 3],
+    #[serde(default)]
+    scattering: [f64; 3],
+    #[serde(default)]
+    asymmetry: Asymmetry,
+    /// Mitsuba `.vol` file relative to the scene file scaling the coefficients, its box is in
+    /// the local space of the object
+    density: Option<PathBuf>,
+}
+
+enum ColorDescription {
+    /// Linear color, not sRGB encoded
+    Color([f64; 3]),
+    Pattern(PatternDescription),
+}
+
+// Picks the variant by the shape of the value instead of trying both like an untagged enum,
+// which would hide why a pattern table is invalid
+impl<'de> Deserialize<'de> for ColorDescription {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ColorVisitor;
+
+        impl<'de> Visitor<'de> for ColorVisitor {
+            type Value = ColorDescription;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a color array or a pattern table")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Deserialize::deserialize(SeqAccessDeserializer::new(seq))
+                    .map(ColorDescription::Color)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                Deserialize::deserialize(MapAccessDeserializer::new(map))
+                    .map(ColorDescription::Pattern)
+            }
+        }
+
+        deserializer.deserialize_any(ColorVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum PatternDescription {
+    /// Image file relative to the scene file, its sRGB colors are decoded to linear
+    Texture {
+        file: PathBuf,
+        #[serde(default = "PatternDescription::default_wrap")]
+        wrap: Wrap,
+        #[serde(default = "PatternDescription::default_filter")]
+        filter: Filter,
+        #[serde(default = "PatternDescription::default_scale")]
+        scale: f64,
+    },
+    Checker {
+        even: [f64; 3],
+        odd: [f64; 3],
+        #[serde(default = "PatternDescription::default_scale")]
+        scale: f64,
+    },
+    Noise {
+        low: [f64; 3],
+        high: [f64; 3],
+        #[serde(default = "PatternDescription::default_scale")]
+        scale: f64,
+    },
+}
+
+impl PatternDescription {
+    fn default_wrap() -> Wrap {
+        Wrap::Repeat
+    }
+
+    fn default_filter() -> Filter {
+        Filter::Bilinear
+    }
+
+    fn default_scale() -> f64 {
+        1.0
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ShapeDescription {
+    Ball {
+        radius: Positive,
+    },
+    Cuboid {
+        half_extents: [Positive; 3],
+    },
+    Plane {
+        normal: Direction,
+    },
+    /// Wavefront OBJ file relative to the scene file, every group becomes its own object
+    Mesh {
+        file: PathBuf,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationDescription {
+    axis: Direction,
+    degree: f64,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "f64")]
+struct Positive(f64);
+
+impl TryFrom<f64> for Positive {
+    type Error = &'static str;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if value > 0.0 {
+            Ok(Positive(value))
+        } else {
+            Err("value must be positive")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "f64")]
+struct NonNegative(f64);
+
+impl TryFrom<f64> for NonNegative {
+    type Error = &'static str;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if value >= 0.0 {
+            Ok(NonNegative(value))
+        } else {
+            Err("value must not be negative")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "f64")]
+struct Turbidity(f64);
+
+impl TryFrom<f64> for Turbidity {
+    type Error = &'static str;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if (1.7..=10.0).contains(&value) {
+            Ok(Turbidity(value))
+        } else {
+            Err("turbidity must be between 1.7 and 10")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "f64")]
+struct ConeAngle(f64);
+
+impl TryFrom<f64> for ConeAngle {
+    type Error = &'static str;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if (0.0..=180.0).contains(&value) {
+            Ok(ConeAngle(value))
+        } else {
+            Err("cone angle must be between 0 and 180 degree")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(try_from = "f64")]
+struct Asymmetry(f64);
+
+impl TryFrom<f64> for Asymmetry {
+    type Error = &'static str;
+
+    fn try_from(value: f64) -> Result<Self, Self::Error> {
+        if value > -1.0 && value < 1.0 {
+            Ok(Asymmetry(value))
+        } else {
+            Err("asymmetry must be between -1 and 1")
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "[f64; 3]")]
+struct Direction(Unit<Vector3<f64>>);
+
+impl TryFrom<[f64; 3]> for Direction {
+    type Error = &'static str;
+
+    fn try_from(value: [f64; 3]) -> Result<Self, Self::Error> {
+        Unit::try_new(Vector3::from(value), f64::EPSILON)
+            .map(Direction)
+            .ok_or("vector must not be zero")
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "String")]
+struct IntegratorName(String);
+
+impl TryFrom<String> for IntegratorName {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if integrator::NAMES.contains(&value.as_str()) {
+            Ok(IntegratorName(value))
+        } else {
+            Err(format!(
+                "unknown integrator '{}', expected one of {}",
+                value,
+                integrator::NAMES.join(", ")
+            ))
+        }
+    }
+}
+
+pub enum Error {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Mesh(PathBuf, tobj::LoadError),
+    Texture(PathBuf, image::ImageError),
+    Volume(PathBuf, io::Error),
+    Environment(PathBuf, image::ImageError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "could not read scene file: {}", error),
+            Error::Parse(error) => write!(f, "invalid scene file: {}", error),
+            Error::Mesh(path, error) => {
+                write!(f, "could not load mesh {}: {}", path.display(), error)
+            }
+            Error::Texture(path, error) => {
+                write!(f, "could not load texture {}: {}", path.display(), error)
+            }
+            Error::Volume(path, error) => {
+                write!(f, "could not load volume {}: {}", path.display(), error)
+            }
+            Error::Environment(path, error) => {
+                write!(
+                    f,
+                    "could not load environment {}: {}",
+                    path.display(),
+                    error
+                )
+            }
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::Parse(error)
+    }
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Scene, Error> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)?;
+    parse(&source, path.parent().unwrap_or_else(|| Path::new("")))
+}
+
+/// Parses a scene description, files referenced by the scene are resolved relative to `directory`.
+pub fn parse(source: &str, directory: &Path) -> Result<Scene, Error> {
+    let description: SceneDescription = toml::from_str(source)?;
+
+    let mut objects = Vec::new();
+    for object in &description.objects {
+        objects.extend(build_objects(object, directory)?);
+    }
+
+    let fog = description
+        .fog
+        .as_ref()
+        .map(|fog| build_medium(fog, directory))
+        .transpose()?;
+
+    let environment = description
+        .environment
+        .as_ref()
+        .map(|environment| build_environment(environment, directory))
+        .transpose()?;
+
+    let mut lights = description
+        .lights
+        .into_iter()
+        .map(build_light)
+        .collect::<Vec<_>>();
+    if let Some(Environment::Sky(sky)) = &environment {
+        lights.push(Light::Directional(sky.sun()));
+    }
+
+    let emitters = objects
+        .iter()
+        .enumerate()
+        .filter(|(_, object)| object.material.is_emissive())
+        .map(|(index, _)| index)
+        .collect();
+
+    Ok(Scene {
+        camera: build_camera(description.camera),
+        width: description.width.get(),
+        height: description.height.get(),
+        max_recursion_depth: description.max_recursion_depth,
+        max_rays: description.max_rays.get(),
+        seed: description.seed,
+        integrator: integrator::by_name(&description.integrator.0).unwrap(),
+        tone_mapping: ToneMapping {
+            operator: description.tone_mapping.operator,
+            exposure: description.tone_mapping.exposure,
+            white_point: description.tone_mapping.white_point.0,
+        },
+        fog,
+        environment,
+        lights,
+        bvh: Bvh::new(&objects),
+        emitters,
+        objects,
+    })
+}
+
+fn build_camera(CameraDescription(description): CameraDescription) -> Camera {
+    let camera = Camera::look_at(
+        Point3::from(description.position),
+        Point3::from(description.target),
+        description.up.0.into_inner(),
+        description.fov.0,
+    );
+
+    let focus_distance = description
+        .focus_distance
+        .map_or(camera.focus_distance, |distance| distance.0);
+    camera.lens(description.aperture, focus_distance)
+}
+
+fn build_environment(
+    description: &EnvironmentDescription,
+    directory: &Path,
+) -> Result<Environment, Error> {
+    let environment = match description {
+        EnvironmentDescription::Image { file, intensity } => {
+            let path = directory.join(file);
+            Environment::Map(
+                EnvironmentMap::load(&path, *intensity)
+                    .map_err(|error| Error::Environment(path, error))?,
+            )
+        }
+        EnvironmentDescription::Sky {
+            sun_direction,
+            turbidity,
+            ground_albedo,
+            intensity,
+        } => Environment::Sky(Sky::new(
+            sun_direction.0.into_inner(),
+            turbidity.0,
+            (*ground_albedo).into(),
+            *intensity,
+        )),
+    };
+
+    Ok(environment)
+}
+
+fn build_light(description: LightDescription) -> Light {
+    match description {
+        LightDescription::Directional {
+            direction,
+            color,
+            intensity,
+        } => Light::Directional(DirectionalLight {
+            direction: direction.0.into_inner(),
+            color: color.into(),
+            intensity,
+        }),
+        LightDescription::Spherical {
+            position,
+            radius,
+            color,
+            intensity,
+        } => Light::Spherical(SphericalLight {
+            position: Point3::from(position),
+            radius: radius.0,
+            color: color.into(),
+            intensity,
+        }),
+        LightDescription::Spot {
+            position,
+            direction,
+            color,
+            intensity,
+            inner_angle,
+            outer_angle,
+        } => Light::Spot(SpotLight {
+            position: Point3::from(position),
+            direction: direction.0.into_inner(),
+            color: color.into(),
+            intensity,
+            // A larger inner cone would turn the smooth edge around
+            inner_angle: inner_angle.0.min(outer_angle.0).to_radians(),
+            outer_angle: outer_angle.0.to_radians(),
+        }),
+        LightDescription::Rectangle {
+            position,
+            normal,
+            size: [width, height],
+            color,
+            intensity,
+        } => Light::Rectangle(RectangleLight {
+            position: Point3::from(position),
+            normal: normal.0.into_inner(),
+            width: width.0,
+            height: height.0,
+            color: color.into(),
+            intensity,
+        }),
+        LightDescription::Disk {
+            position,
+            normal,
+            radius,
+            color,
+            intensity,
+        } => Light::Disk(DiskLight {
+            position: Point3::from(position),
+            normal: normal.0.into_inner(),
+            radius: radius.0,
+            color: color.into(),
+            intensity,
+        }),
+    }
+}
+
+fn build_medium(description: &MediumDescription, directory: &Path) -> Result<Medium, Error> {
+    let density = match &description.density {
+        Some(file) => {
+            let path = directory.join(file);
+            Some(VoxelGrid::load(&path).map_err(|error| Error::Volume(path, error))?)
+        }
+        None => None,
+    };
+
+    Ok(Medium {
+        absorption: description.absorption.into(),
+        scattering: description.scattering.into(),
+        asymmetry: description.asymmetry.0,
+        density,
+    })
+}
+
+fn build_objects(description: &ObjectDescription, directory: &Path) -> Result<Vec<Object>, Error> {
+    let color = description
+        .color
+        .as_ref()
+        .map(|color| build_coloration(color, directory))
+        .transpose()?;
+    let color = color.as_ref();
+    let medium = description
+        .medium
+        .as_ref()
+        .map(|medium| build_medium(medium, directory))
+        .transpose()?;
+    let medium = medium.as_ref();
+
+    let objects = match &description.shape {
+        ShapeDescription::Ball { radius } => vec![apply_object(
+            ObjectBuilder::new(shape::Ball::new(radius.0)),
+            description,
+            color,
+            medium,
+        )],
+        ShapeDescription::Cuboid {
+            half_extents: [x, y, z],
+        } => vec![apply_object(
+            ObjectBuilder::new(shape::Cuboid::new(Vector3::new(x.0, y.0, z.0))),
+            description,
+            color,
+            medium,
+        )],
+        ShapeDescription::Plane { normal } => vec![apply_object(
+            ObjectBuilder::new(shape::Plane::new(normal.0)),
+            description,
+            color,
+            medium,
+        )],
+        ShapeDescription::Mesh { file } => {
+            let path = directory.join(file);
+            mesh::load_obj(&path)
+                .map_err(|error| Error::Mesh(path, error))?
+                .into_iter()
+                .map(|mesh| {
+                    apply_object(
+                        ObjectBuilder::new(mesh.shape).vertex_normals(mesh.normals),
+                        description,
+                        color,
+                        medium,
+                    )
+                })
+                .collect()
+        }
+    };
+
+    Ok(objects)
+}
+
+fn build_coloration(description: &ColorDescription, directory: &Path) -> Result<Coloration, Error> {
+    let coloration = match description {
+        ColorDescription::Color(color) => Coloration::Color((*color).into()),
+        ColorDescription::Pattern(PatternDescription::Texture {
+            file,
+            wrap,
+            filter,
+            scale,
+        }) => {
+            let path = directory.join(file);
+            Coloration::Texture {
+                texture: Texture::load(&path, *wrap, *filter)
+                    .map_err(|error| Error::Texture(path, error))?,
+                scale: *scale,
+            }
+        }
+        ColorDescription::Pattern(PatternDescription::Checker { even, odd, scale }) => {
+            Coloration::Checker {
+                even: (*even).into(),
+                odd: (*odd).into(),
+                scale: *scale,
+            }
+        }
+        ColorDescription::Pattern(PatternDescription::Noise { low, high, scale }) => {
+            Coloration::Noise {
+                low: (*low).into(),
+                high: (*high).into(),
+                scale: *scale,
+            }
+        }
+    };
+
+    Ok(coloration)
+}
+
+fn apply_object<S: Shape<f64>>(
+    mut builder: ObjectBuilder<S>,
+    description: &ObjectDescription,
+    color: Option<&Coloration>,
+    medium: Option<&Medium>,
+) -> Object {
+    let [x, y, z] = description.position;
+    builder = builder.position(x, y, z);
+
+    if let Some(rotation) = &description.rotation {
+        builder = builder.rotation(rotation.axis.0.into_inner(), rotation.degree);
+    }
+
+    if let Some(color) = color {
+        builder = builder.color(color.clone());
+    }
+
+    if let Some(albedo) = description.albedo {
+        builder = builder.albedo(albedo);
+    }
+
+    if let Some(surface) = &description.surface {
+        builder = builder.surface(surface.clone());
+    }
+
+    if let Some(emission) = description.emission {
+        builder = builder.emission(emission);
+    }
+
+    if let Some(medium) = medium {
+        builder = builder.medium(medium.clone());
+    }
+
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = r#"
+        max_recursion_depth = 1
+        max_rays = 1
+
+        [camera]
+        position = [0.0, 0.0, 1.0]
+        target = [0.0, 0.0, 0.0]
+        fov = 60.0
+
+        [[objects]]
+        shape = { type = "ball", radius = 0.5 }
+    "#;
+
+    fn parse_color(color: &str) -> Result<Scene, Error> {
+        parse(&format!("{}color = {}\n", SCENE, color), Path::new(""))
+    }
+
+    #[test]
+    fn parses_colors_and_patterns() {
+        assert!(parse_color("[1.0, 0.5, 0.0]").is_ok());
+        assert!(parse_color(
+            r#"{ type = "checker", even = [1.0, 1.0, 1.0], odd = [0.0, 0.0, 0.0] }"#
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn keeps_pattern_errors() {
+        let error =
+            parse_color(r#"{ type = "checker", even = [1.0, 1.0, 1.0], od = [0.0, 0.0, 0.0] }"#)
+                .err()
+                .unwrap()
+                .to_string();
+        assert!(error.contains("unknown field `od`"), "{}", error);
+
+        let error = parse_color(r#"{ type = "stripes" }"#)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("unknown variant `stripes`"), "{}", error);
+
+        let error = parse_color("[1.0, 0.5]").err().unwrap().to_string();
+        assert!(error.contains("invalid length 2"), "{}", error);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::color::Color;
+use crate::light::DirectionalLight;
+use nalgebra::Vector3;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+/// Scales the luminance of the model in kcd/m² to the radiance of the renderer
+const LUMINANCE_SCALE: f64 = 0.06;
+
+/// Irradiance of the sun at the top of the atmosphere before it is scaled by the intensity
+const SUN_IRRADIANCE: f64 = 4.0;
+
+/// Analytic daylight sky after Preetham, Shirley and Smits, "A Practical Analytic Model for
+/// Daylight". Below the horizon the sky is replaced by a diffuse ground.
+#[derive(Clone)]
+pub struct Sky {
+    /// Direction towards the sun
+    to_sun: Vector3<f64>,
+    turbidity: f64,
+    intensity: f64,
+    /// Luminance Y and chromaticity x and y at the zenith
+    zenith: [f64; 3],
+    /// Coefficients of the Perez distribution for Y, x and y
+    perez: [[f64; 5]; 3],
+    ground: Color,
+}
+
+impl Sky {
+    /// `sun_direction` is the direction sunlight travels, like the direction of a directional
+    /// light. A turbidity of 2 is a clear and 10 a hazy sky.
+    pub fn new(
+        sun_direction: Vector3<f64>,
+        turbidity: f64,
+        ground_albedo: Color,
+        intensity: f64,
+    ) -> Sky {
+        let to_sun = -sun_direction.normalize();
+        // The model does not hold for a sun below the horizon
+        let theta_sun = to_sun.y.clamp(0.0, 1.0).acos().min(FRAC_PI_2 - 1e-3);
+        let t = turbidity;
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let (s, s2, s3) = (theta_sun, theta_sun * theta_sun, theta_sun.powi(3));
+        let zenith_x = t * t * (0.00166 * s3 - 0.00375 * s2 + 0.00209 * s)
+            + t * (-0.02903 * s3 + 0.06377 * s2 - 0.03202 * s + 0.00394)
+            + (0.11693 * s3 - 0.21196 * s2 + 0.06052 * s + 0.25886);
+        let zenith_y = t * t * (0.00275 * s3 - 0.00610 * s2 + 0.00317 * s)
+            + t * (-0.04214 * s3 + 0.08970 * s2 - 0.04153 * s + 0.00516)
+            + (0.15346 * s3 - 0.26756 * s2 + 0.06670 * s + 0.26688);
+
+        let mut sky = Sky {
+            to_sun,
+            turbidity,
+            intensity,
+            zenith: [zenith_luminance.max(0.0), zenith_x, zenith_y],
+            perez,
+            ground: Color([0.0; 3]),
+        };
+
+        // The ground reflects the sun and roughly the light of the sky above it
+        let sun_irradiance = sky.sun().color * (sky.sun().intensity * to_sun.y.max(0.0));
+        let sky_irradiance = sky.radiance(&Vector3::y()) * PI;
+        sky.ground = ground_albedo * (sun_irradiance + sky_irradiance) / PI;
+        sky
+    }
+
+    /// Light arriving from `direction`.
+    pub fn radiance(&self, direction: &Vector3<f64>) -> Color {
+        if direction.y < 0.0 {
+            return self.ground;
+        }
+
+        let cos_theta = direction.y.max(1e-3);
+        let gamma = direction.dot(&self.to_sun).clamp(-1.0, 1.0).acos();
+        let theta_sun = self.to_sun.y.clamp(0.0, 1.0).acos().min(FRAC_PI_2 - 1e-3);
+
+        let value = |channel: usize| {
+            let coefficients = &self.perez[channel];
+            self.zenith[channel] * perez(coefficients, cos_theta, gamma)
+                / perez(coefficients, 1.0, theta_sun)
+        };
+        let (luminance, x, y) = (value(0), value(1), value(2));
+
+        xyy_to_rgb(luminance * LUMINANCE_SCALE, x, y) * self.intensity
+    }
+
+    /// The sun as a directional light, dimmed and reddened by the air it passes through.
+    pub fn sun(&self) -> DirectionalLight {
+        // Relative air mass after Kasten and Young
+        let elevation = self.to_sun.y.clamp(0.0, 1.0).asin().to_degrees();
+        let air_mass =
+            1.0 / (self.to_sun.y.max(0.0) + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+
+        // Rayleigh scattering grows towards blue, haze dims all colors alike
+        let rayleigh = [0.06, 0.12, 0.27];
+        let haze = 0.02 * self.turbidity;
+        let [r, g, b] = rayleigh;
+        let color = Color([
+            (-(r + haze) * air_mass).exp(),
+            (-(g + haze) * air_mass).exp(),
+            (-(b + haze) * air_mass).exp(),
+        ]);
+
+        DirectionalLight {
+            direction: -self.to_sun,
+            color,
+            intensity: if self.to_sun.y > 0.0 {
+                SUN_IRRADIANCE * self.intensity
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Perez et al. distribution of the sky brightness over the zenith angle `theta` and the
+/// angle `gamma` to the sun.
+fn perez([a, b, c, d, e]: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// Converts luminance and chromaticity to linear sRGB.
+fn xyy_to_rgb(luminance: f64, x: f64, y: f64) -> Color {
+    let big_x = x * luminance / y;
+    let big_z = (1.0 - x - y) * luminance / y;
+    Color([
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    ])
+}
@@ -0,0 +1,141 @@
+use crate::color::Color;
+use image::{ImageBuffer, ImageResult, Rgb};
+use nalgebra::Point2;
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Filter {
+    Nearest,
+    Bilinear,
+}
+
+/// Linear colors of the texels, decoded from sRGB once when loading.
+type LinearImage = ImageBuffer<Rgb<f32>, Vec<f32>>;
+
+#[derive(Clone)]
+pub struct Texture {
+    image: Arc<LinearImage>,
+    pub wrap: Wrap,
+    pub filter: Filter,
+}
+
+impl Texture {
+    pub fn load(path: impl AsRef<Path>, wrap: Wrap, filter: Filter) -> ImageResult<Texture> {
+        let image = image::open(path)?.into_rgb8();
+        let linear = LinearImage::from_fn(image.width(), image.height(), |x, y| {
+            let Color([r, g, b]) = image.get_pixel(x, y).0.into();
+            Rgb([r as f32, g as f32, b as f32])
+        });
+        Ok(Texture {
+            image: Arc::new(linear),
+            wrap,
+            filter,
+        })
+    }
+
+    pub fn color_at(&self, texture_coords: &Point2<f64>) -> Color {
+        // Texture coordinates start at the bottom left, image rows at the top
+        let x = texture_coords.x * self.image.width() as f64;
+        let y = (1.0 - texture_coords.y) * self.image.height() as f64;
+
+        match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // Texel centers are at half integer coordinates
+                let x = x - 0.5;
+                let y = y - 0.5;
+                let x0 = x.floor();
+                let y0 = y.floor();
+                let tx = x - x0;
+                let ty = y - y0;
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = self.texel(x0, y0) * (1.0 - tx) + self.texel(x0 + 1, y0) * tx;
+                let bottom = self.texel(x0, y0 + 1) * (1.0 - tx) + self.texel(x0 + 1, y0 + 1) * tx;
+                top * (1.0 - ty) + bottom * ty
+            }
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = Self::wrap(x, self.image.width(), self.wrap);
+        let y = Self::wrap(y, self.image.height(), self.wrap);
+        let [r, g, b] = self.image.get_pixel(x, y).0;
+        Color([r as f64, g as f64, b as f64])
+    }
+
+    fn wrap(coord: i64, bound: u32, wrap: Wrap) -> u32 {
+        let bound = bound as i64;
+        match wrap {
+            Wrap::Repeat => coord.rem_euclid(bound) as u32,
+            Wrap::Clamp => coord.clamp(0, bound - 1) as u32,
+        }
+    }
+}
+
+pub fn checker(texture_coords: &Point2<f64>, even: Color, odd: Color) -> Color {
+    let cell = texture_coords.x.floor() + texture_coords.y.floor();
+    if cell.rem_euclid(2.0) < 1.0 {
+        even
+    } else {
+        odd
+    }
+}
+
+/// Fractal value noise in the range [0, 1].
+pub fn noise(texture_coords: &Point2<f64>) -> f64 {
+    const OCTAVES: u32 = 5;
+
+    let (value, amplitude_sum, _, _) = (0..OCTAVES).fold(
+        (0.0, 0.0, 1.0, 1.0),
+        |(value, amplitude_sum, amplitude, frequency), _| {
+            (
+                value
+                    + value_noise(texture_coords.x * frequency, texture_coords.y * frequency)
+                        * amplitude,
+                amplitude_sum + amplitude,
+                amplitude * 0.5,
+                frequency * 2.0,
+            )
+        },
+    );
+
+    value / amplitude_sum
+}
+
+fn value_noise(x: f64, y: f64) -> f64 {
+    let x0 = x.floor();
+    let y0 = y.floor();
+    let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+    let tx = smooth(x - x0);
+    let ty = smooth(y - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+
+    let top = lerp(hash(x0, y0), hash(x0 + 1, y0), tx);
+    let bottom = lerp(hash(x0, y0 + 1), hash(x0 + 1, y0 + 1), tx);
+    lerp(top, bottom, ty)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Pseudo random value in the range [0, 1] for a lattice point.
+fn hash(x: i64, y: i64) -> f64 {
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    h ^= h >> 33;
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
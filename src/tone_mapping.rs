@@ -0,0 +1,173 @@
+use crate::color::Color;
+use serde::Deserialize;
+
+/// Names accepted by `Operator::from_name`, as written in scene files.
+pub const NAMES: [&str; 5] = [
+    "clamp",
+    "reinhard",
+    "extended_reinhard",
+    "aces",
+    "uncharted2",
+];
+
+/// Curve compressing the unbounded radiance of the framebuffer into the displayable range.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    /// Cuts off everything brighter than one
+    Clamp,
+    Reinhard,
+    /// Reinhard reaching white at the white point instead of never
+    ExtendedReinhard,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+    /// Hable's filmic curve from Uncharted 2
+    Uncharted2,
+}
+
+impl Operator {
+    pub fn from_name(name: &str) -> Option<Operator> {
+        match name {
+            "clamp" => Some(Operator::Clamp),
+            "reinhard" => Some(Operator::Reinhard),
+            "extended_reinhard" => Some(Operator::ExtendedReinhard),
+            "aces" => Some(Operator::Aces),
+            "uncharted2" => Some(Operator::Uncharted2),
+            _ => None,
+        }
+    }
+}
+
+pub struct ToneMapping {
+    pub operator: Operator,
+    /// Brightens the image by powers of two before the curve is applied
+    pub exposure: f64,
+    /// Radiance mapped to white by the extended Reinhard curve
+    pub white_point: f64,
+}
+
+impl ToneMapping {
+    /// Maps linear radiance to a color between zero and one.
+    pub fn apply(&self, color: Color) -> Color {
+        let color = color * 2f64.powf(self.exposure);
+        let [r, g, b] = color.0;
+        let map = |value: f64| self.map(value.max(0.0));
+        Color([map(r), map(g), map(b)]).clamp()
+    }
+
+    fn map(&self, value: f64) -> f64 {
+        match self.operator {
+            Operator::Clamp => value,
+            Operator::Reinhard => value / (1.0 + value),
+            Operator::ExtendedReinhard => {
+                let white_squared = self.white_point * self.white_point;
+                value * (1.0 + value / white_squared) / (1.0 + value)
+            }
+            Operator::Aces => {
+                (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)
+            }
+            Operator::Uncharted2 => {
+                const EXPOSURE_BIAS: f64 = 2.0;
+                const WHITE: f64 = 11.2;
+                uncharted2(value * EXPOSURE_BIAS) / uncharted2(WHITE)
+            }
+        }
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: Operator::Clamp,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+fn uncharted2(value: f64) -> f64 {
+    const A: f64 = 0.15;
+    const B: f64 = 0.50;
+    const C: f64 = 0.10;
+    const D: f64 = 0.20;
+    const E: f64 = 0.02;
+    const F: f64 = 0.30;
+    (value * (A * value + C * B) + D * E) / (value * (A * value + B) + D * F) - E / F
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(operator: Operator, value: f64) -> f64 {
+        let tone_mapping = ToneMapping {
+            operator,
+            ..ToneMapping::default()
+        };
+        tone_mapping.apply(Color([value; 3])).0[0]
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn names_match_scene_files() {
+        for &name in &NAMES {
+            let operator = Operator::from_name(name).unwrap();
+            let parsed: Operator = toml::Value::String(name.into()).try_into().unwrap();
+            assert_eq!(operator, parsed, "{}", name);
+        }
+        assert_eq!(Operator::from_name("uncharted"), None);
+    }
+
+    #[test]
+    fn black_stays_black() {
+        for &name in &NAMES {
+            assert_close(map(Operator::from_name(name).unwrap(), 0.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn maps_one() {
+        assert_close(map(Operator::Clamp, 1.0), 1.0);
+        assert_close(map(Operator::Reinhard, 1.0), 0.5);
+        assert_close(map(Operator::ExtendedReinhard, 1.0), 0.53125);
+        assert_close(map(Operator::Aces, 1.0), 2.54 / 3.16);
+        assert_close(
+            map(Operator::Uncharted2, 1.0),
+            uncharted2(2.0) / uncharted2(11.2),
+        );
+    }
+
+    #[test]
+    fn white_point_maps_to_white() {
+        assert_close(map(Operator::ExtendedReinhard, 4.0), 1.0);
+        // Hable's curve reaches white at its white point before the exposure bias
+        assert_close(map(Operator::Uncharted2, 5.6), 1.0);
+    }
+
+    #[test]
+    fn large_values_stay_in_range() {
+        for &name in &NAMES {
+            let operator = Operator::from_name(name).unwrap();
+            let value = map(operator, 1e6);
+            assert!(value > 0.99 && value <= 1.0, "{} maps to {}", name, value);
+        }
+        assert!(map(Operator::Reinhard, 100.0) < 1.0);
+    }
+
+    #[test]
+    fn exposure_doubles_per_stop() {
+        let tone_mapping = ToneMapping {
+            exposure: 1.0,
+            ..ToneMapping::default()
+        };
+        assert_close(tone_mapping.apply(Color([0.25; 3])).0[0], 0.5);
+    }
+}
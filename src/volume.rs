@@ -0,0 +1,206 @@
+use nalgebra::{Isometry3, Point3, Vector3};
+use ncollide3d::query::Ray;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Size of the header of a `.vol` file in bytes
+const HEADER_SIZE: usize = 48;
+
+/// Densities on a regular grid of voxels stretched over a box.
+#[derive(Clone)]
+pub struct VoxelGrid {
+    densities: Arc<Vec<f32>>,
+    resolution: [usize; 3],
+    min: Point3<f64>,
+    max: Point3<f64>,
+    /// Highest density of the grid, no density between the voxels is higher
+    pub max_density: f64,
+    /// Placement of the grid, its box is given in the local space
+    pub isometry: Isometry3<f64>,
+}
+
+impl VoxelGrid {
+    /// Loads a grid from a Mitsuba `.vol` file with 32 bit float values. Only the first channel
+    /// is used, the box of the file becomes the box of the grid.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<VoxelGrid> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    fn parse(bytes: &[u8]) -> io::Result<VoxelGrid> {
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+
+        if bytes.len() < HEADER_SIZE || &bytes[..3] != b"VOL" || bytes[3] != 3 {
+            return Err(invalid("not a version 3 volume file"));
+        }
+
+        let word = |index: usize| -> [u8; 4] {
+            let start = 4 + index * 4;
+            bytes[start..start + 4].try_into().unwrap()
+        };
+        let int = |index: usize| i32::from_le_bytes(word(index));
+        let float = |index: usize| f32::from_le_bytes(word(index)) as f64;
+
+        if int(0) != 1 {
+            return Err(invalid("only 32 bit float volumes are supported"));
+        }
+        if int(1) <= 0 || int(2) <= 0 || int(3) <= 0 || int(4) <= 0 {
+            return Err(invalid("volume resolution and channels must be positive"));
+        }
+
+        let resolution = [int(1) as usize, int(2) as usize, int(3) as usize];
+        let channels = int(4) as usize;
+        let count = resolution
+            .iter()
+            .try_fold(1usize, |count, &size| count.checked_mul(size));
+        let size = count
+            .and_then(|count| count.checked_mul(channels))
+            .and_then(|values| values.checked_mul(4))
+            .and_then(|size| size.checked_add(HEADER_SIZE));
+        let (count, size) = match (count, size) {
+            (Some(count), Some(size)) => (count, size),
+            _ => return Err(invalid("volume resolution is too large")),
+        };
+        if bytes.len() < size {
+            return Err(invalid("volume file is truncated"));
+        }
+
+        let densities = bytes[HEADER_SIZE..]
+            .chunks_exact(4)
+            .step_by(channels)
+            .take(count)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()).max(0.0))
+            .collect::<Vec<_>>();
+        let max_density = densities.iter().cloned().fold(0.0, f32::max) as f64;
+
+        Ok(VoxelGrid {
+            densities: Arc::new(densities),
+            resolution,
+            min: Point3::new(float(5), float(6), float(7)),
+            max: Point3::new(float(8), float(9), float(10)),
+            max_density,
+            isometry: Isometry3::identity(),
+        })
+    }
+
+    /// Range of distances along the ray inside the box of the grid.
+    pub fn clip(&self, ray: &Ray<f64>) -> Option<(f64, f64)> {
+        let origin = self.isometry.inverse_transform_point(&ray.origin);
+        let dir = self.isometry.inverse_transform_vector(&ray.dir);
+
+        let (mut near, mut far) = (0.0, f64::INFINITY);
+        for axis in 0..3 {
+            let first = (self.min[axis] - origin[axis]) / dir[axis];
+            let second = (self.max[axis] - origin[axis]) / dir[axis];
+            // Rays parallel to a slab give NaN which the comparisons ignore
+            near = first.min(second).max(near);
+            far = first.max(second).min(far);
+        }
+
+        (near < far).then(|| (near, far))
+    }
+
+    /// Trilinearly interpolated density at a point, zero outside the box.
+    pub fn density_at(&self, point: &Point3<f64>) -> f64 {
+        let local = self.isometry.inverse_transform_point(point);
+        let size = self.max - self.min;
+        let relative = (local - self.min).component_div(&size);
+        if relative.iter().any(|&value| !(0.0..=1.0).contains(&value)) {
+            return 0.0;
+        }
+
+        // Voxel centers are at half integer coordinates
+        let coords = Vector3::new(
+            relative.x * self.resolution[0] as f64 - 0.5,
+            relative.y * self.resolution[1] as f64 - 0.5,
+            relative.z * self.resolution[2] as f64 - 0.5,
+        );
+        let base = coords.map(f64::floor);
+        let t = coords - base;
+        let (x, y, z) = (base.x as i64, base.y as i64, base.z as i64);
+
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        let row = |y: i64, z: i64| lerp(self.voxel(x, y, z), self.voxel(x + 1, y, z), t.x);
+        let slice = |z: i64| lerp(row(y, z), row(y + 1, z), t.y);
+        lerp(slice(z), slice(z + 1), t.z)
+    }
+
+    fn voxel(&self, x: i64, y: i64, z: i64) -> f64 {
+        let [width, height, depth] = self.resolution;
+        let x = x.clamp(0, width as i64 - 1) as usize;
+        let y = y.clamp(0, height as i64 - 1) as usize;
+        let z = z.clamp(0, depth as i64 - 1) as usize;
+        self.densities[(z * height + y) * width + x] as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header of a float volume with a unit box followed by `values`.
+    fn volume(resolution: [i32; 3], channels: i32, values: &[f32]) -> Vec<u8> {
+        let mut bytes = b"VOL".to_vec();
+        bytes.push(3);
+        for int in [1, resolution[0], resolution[1], resolution[2], channels].iter() {
+            bytes.extend_from_slice(&int.to_le_bytes());
+        }
+        for float in [0f32, 0.0, 0.0, 1.0, 1.0, 1.0].iter().chain(values) {
+            bytes.extend_from_slice(&float.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn error(bytes: &[u8]) -> ErrorKind {
+        VoxelGrid::parse(bytes).err().unwrap().kind()
+    }
+
+    #[test]
+    fn parses_header_and_first_channel() {
+        let values = [1.0, 9.0, 2.0, 9.0, -3.0, 9.0, 4.0, 9.0];
+        let grid = VoxelGrid::parse(&volume([2, 2, 1], 2, &values))
+            .ok()
+            .unwrap();
+
+        assert_eq!(grid.resolution, [2, 2, 1]);
+        assert_eq!(grid.min, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(grid.max, Point3::new(1.0, 1.0, 1.0));
+        // Negative densities are clamped to zero
+        assert_eq!(*grid.densities, vec![1.0, 2.0, 0.0, 4.0]);
+        assert_eq!(grid.max_density, 4.0);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let mut wrong_magic = volume([1, 1, 1], 1, &[1.0]);
+        wrong_magic[0] = b'X';
+        assert_eq!(error(&wrong_magic), ErrorKind::InvalidData);
+
+        let mut wrong_version = volume([1, 1, 1], 1, &[1.0]);
+        wrong_version[3] = 2;
+        assert_eq!(error(&wrong_version), ErrorKind::InvalidData);
+
+        assert_eq!(error(&volume([1, 0, 1], 1, &[])), ErrorKind::InvalidData);
+        assert_eq!(error(&volume([1, 1, 1], -1, &[])), ErrorKind::InvalidData);
+        assert_eq!(error(&[b'V', b'O', b'L', 3]), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        assert_eq!(
+            error(&volume([2, 2, 2], 1, &[1.0; 7])),
+            ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn rejects_overflowing_resolution() {
+        let huge = i32::MAX;
+        assert_eq!(
+            error(&volume([huge, huge, huge], huge, &[])),
+            ErrorKind::InvalidData
+        );
+    }
+}